@@ -0,0 +1,5 @@
+fn main() {
+    if let Err(error) = regex_map::build::Rules::new("routes", "routes.rules").write() {
+        panic!("{}", error);
+    }
+}
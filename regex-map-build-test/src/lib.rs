@@ -0,0 +1,20 @@
+//! A crate whose build script compiles `routes.rules` with `regex_map::build::Rules`, checking that
+//! `regex_map::include_regex_map!` loads the result.
+
+use regex_map::RegexMap;
+
+/// The map compiled from `routes.rules`.
+///
+/// ```
+/// let routes = regex_map_build_test::routes();
+///
+/// assert_eq!(routes.len(), 4);
+/// assert_eq!(routes.get_first("/api/users/42"), Some(&"user"));
+/// assert_eq!(routes.get_first("/api/health"), Some(&"api"));
+/// assert_eq!(routes.get("/healthz").collect::<Vec<_>>(), vec![&"health"]);
+/// assert_eq!(routes.get_first("/index.css"), Some(&"static"));
+/// assert_eq!(routes.get_first("/index.html"), None);
+/// ```
+pub fn routes() -> &'static RegexMap<&'static str> {
+    regex_map::include_regex_map!("routes")
+}
@@ -0,0 +1,218 @@
+//! Procedural macros for the `regex-map` crate, re-exported by it when its `macros` feature is enabled.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, LitStr, Token};
+
+/// Create a lazily initialized `RegexMap` from `"pattern" => value` pairs, checking every pattern at
+/// compile time.
+///
+/// The map is built on first use, which makes the macro suitable for initializing a `static`.
+///
+/// ```
+/// use std::sync::LazyLock;
+///
+/// use regex_map::{regex_map, RegexMap};
+///
+/// static ROUTES: LazyLock<RegexMap<&str>> = regex_map! {
+///     "^/api/" => "api",
+///     "^/api/users/[0-9]+$" => "user",
+/// };
+///
+/// assert_eq!(ROUTES.get("/api/users/42").cloned().collect::<Vec<_>>(), vec!["api", "user"]);
+///
+/// static EMPTY: LazyLock<RegexMap<i32>> = regex_map! {};
+///
+/// assert!(EMPTY.is_empty());
+/// ```
+///
+/// An invalid pattern is a compile error pointing at it:
+///
+/// ```compile_fail
+/// use std::sync::LazyLock;
+///
+/// use regex_map::{regex_map, RegexMap};
+///
+/// static ROUTES: LazyLock<RegexMap<&str>> = regex_map! {
+///     "^/api/(" => "api",
+/// };
+/// ```
+#[proc_macro]
+pub fn regex_map(input: TokenStream) -> TokenStream {
+    let entries = parse_macro_input!(input as Entries).0;
+
+    if let Err(errors) = check(entries.iter().map(|entry| &entry.pattern)) {
+        // The macro is used as an expression, in which several `compile_error!`s need a block.
+        let errors = errors.to_compile_error();
+        return quote!({ #errors ::std::unreachable!() }).into();
+    }
+
+    let patterns = entries.iter().map(|entry| &entry.pattern);
+    let values = entries.iter().map(|entry| &entry.value);
+    quote! {
+        ::std::sync::LazyLock::new(|| {
+            ::regex_map::RegexMap::new::<_, &'static str>([#((#patterns, #values)),*])
+        })
+    }
+    .into()
+}
+
+/// Implement `regex_map::RegexMatch` for an enum of fieldless variants, each matched by the regular
+/// expressions of its `#[regex("...")]` attributes.
+///
+/// The regular expressions are checked at compile time and all variants share a single `RegexMap`,
+/// built on first use. Variants are reported in declaration order; those without a `#[regex]`
+/// attribute are never matched.
+///
+/// ```
+/// use regex_map::RegexMatch;
+///
+/// #[derive(Debug, PartialEq, RegexMatch)]
+/// enum Level {
+///     #[regex("(?i)error")]
+///     #[regex("(?i)fatal")]
+///     Error,
+///     #[regex("(?i)warn")]
+///     Warning,
+///     Other,
+/// }
+///
+/// assert_eq!(Level::matches("Fatal: warning").collect::<Vec<_>>(), vec![Level::Error, Level::Warning]);
+/// assert_eq!(Level::first_match("WARN: disk"), Some(Level::Warning));
+/// assert_eq!(Level::first_match("info"), None);
+///
+/// #[derive(Debug, PartialEq, RegexMatch)]
+/// enum Unmatched {
+///     A,
+///     B,
+/// }
+///
+/// assert_eq!(Unmatched::matches("A").count(), 0);
+/// assert_eq!(Unmatched::first_match("B"), None);
+/// ```
+#[proc_macro_derive(RegexMatch, attributes(regex))]
+pub fn derive_regex_match(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match regex_match(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(errors) => errors.to_compile_error().into(),
+    }
+}
+
+fn regex_match(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "RegexMatch can only be derived for enums",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "RegexMatch cannot be derived for generic enums",
+        ));
+    }
+
+    let mut patterns = Vec::new();
+    let mut indices = Vec::new();
+    for (index, variant) in data.variants.iter().enumerate() {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "RegexMatch can only be derived for enums of fieldless variants",
+            ));
+        }
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("regex"))
+        {
+            patterns.push(attr.parse_args::<LitStr>()?);
+            indices.push(index);
+        }
+    }
+    check(&patterns)?;
+
+    let name = &input.ident;
+    let variants = data.variants.iter().map(|variant| &variant.ident);
+    let index = 0..data.variants.len();
+    Ok(quote! {
+        const _: () = {
+            static MAP: ::std::sync::LazyLock<::regex_map::RegexMap<usize>> =
+                ::std::sync::LazyLock::new(|| {
+                    ::regex_map::RegexMap::new::<_, &'static str>([#((#patterns, #indices)),*])
+                });
+
+            fn variant(index: usize) -> #name {
+                match index {
+                    #(#index => #name::#variants,)*
+                    _ => ::std::unreachable!(),
+                }
+            }
+
+            impl ::regex_map::RegexMatch for #name {
+                fn matches(s: &str) -> impl ::std::iter::Iterator<Item = Self> {
+                    let mut indices = MAP.get(s).copied().collect::<::std::vec::Vec<_>>();
+                    indices.dedup();
+                    indices.into_iter().map(variant)
+                }
+
+                fn first_match(s: &str) -> ::std::option::Option<Self> {
+                    MAP.get_first(s).copied().map(variant)
+                }
+            }
+        };
+    })
+}
+
+/// Check that every pattern is a valid regular expression, reporting every invalid one at its span.
+fn check<'a, I>(patterns: I) -> syn::Result<()>
+where
+    I: IntoIterator<Item = &'a LitStr>,
+{
+    let mut errors = None::<syn::Error>;
+    for pattern in patterns {
+        if let Err(error) = regex_syntax::parse(&pattern.value()) {
+            let error = syn::Error::new(
+                pattern.span(),
+                format!("invalid regular expression: {}", error),
+            );
+            match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            }
+        }
+    }
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(()),
+    }
+}
+
+/// The `"pattern" => value` pairs of `regex_map!`, separated by commas.
+struct Entries(Punctuated<Entry, Token![,]>);
+
+struct Entry {
+    pattern: LitStr,
+    value: Expr,
+}
+
+impl Parse for Entries {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        Punctuated::parse_terminated(input).map(Entries)
+    }
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let pattern = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let value = input.parse()?;
+        Ok(Entry { pattern, value })
+    }
+}
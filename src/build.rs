@@ -0,0 +1,283 @@
+//! Maps compiled at build time and embedded in the binary, enabled by the `dfa` feature.
+//!
+//! A rules file lists one entry per line, as a name followed by whitespace and a regular expression,
+//! which extends to the end of the line. Empty lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # name    pattern
+//! health    ^/healthz$
+//! user      ^/api/users/[0-9]+$
+//! api       ^/api/
+//! ```
+//!
+//! The build script of the crate compiles it with `Rules`, which fails the build on an invalid rule:
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     if let Err(error) = regex_map::build::Rules::new("routes", "routes.rules").write() {
+//!         panic!("{}", error);
+//!     }
+//! }
+//! ```
+//!
+//! and `include_regex_map!` embeds it, as a `&'static RegexMap<&'static str>` whose values are the names
+//! of the rules:
+//!
+//! ```ignore
+//! fn routes() -> &'static regex_map::RegexMap<&'static str> {
+//!     regex_map::include_regex_map!("routes")
+//! }
+//!
+//! assert_eq!(routes().get_first("/api/users/42"), Some(&"user"));
+//! ```
+//!
+//! The map is loaded from the embedded DFA on first use, without compiling any regular expression.
+//! The `regex-map-build-test` crate of the repository is a complete example.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::dfa::{DfaError, DfaKind};
+use crate::error::Error;
+use crate::pattern::Anchor;
+use crate::string::RegexMapBuilder;
+
+/// Compiles a rules file into the output directory of a build script, for `include_regex_map!`.
+///
+/// ```
+/// use regex_map::build::Rules;
+/// use regex_map::RegexMap;
+///
+/// let dir = std::env::temp_dir().join("regex-map-rules-doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("routes.rules"), "health ^/healthz$\napi ^/api/\n").unwrap();
+///
+/// Rules::new("routes", dir.join("routes.rules")).out_dir(&dir).write().unwrap();
+///
+/// let bytes = std::fs::read(dir.join("routes.regexmap")).unwrap();
+/// let map = RegexMap::from_bytes(&bytes, ["health", "api"]).unwrap();
+/// assert_eq!(map.get_first("/api/users"), Some(&"api"));
+///
+/// std::fs::write(dir.join("spaced.rules"), "  padded  ^a $\n").unwrap();
+/// Rules::new("spaced", dir.join("spaced.rules")).out_dir(&dir).write().unwrap();
+/// let bytes = std::fs::read(dir.join("spaced.regexmap")).unwrap();
+/// let map = RegexMap::from_bytes(&bytes, ["padded"]).unwrap();
+/// assert_eq!(map.get_first("a "), Some(&"padded"));
+/// assert_eq!(map.get_first("a"), None);
+///
+/// std::fs::write(dir.join("broken.rules"), "health ^/healthz$\napi ^/api/(\n").unwrap();
+/// let error = Rules::new("broken", dir.join("broken.rules")).out_dir(&dir).write().unwrap_err();
+/// assert!(error.to_string().contains("broken.rules:2: "));
+/// ```
+pub struct Rules {
+    name: String,
+    path: PathBuf,
+    out_dir: Option<PathBuf>,
+    kind: DfaKind,
+    anchor: Anchor,
+    case_insensitive: bool,
+}
+
+impl Rules {
+    /// Compile the rules file at `path` under `name`, the argument of `include_regex_map!`.
+    pub fn new<P: AsRef<Path>>(name: &str, path: P) -> Self {
+        Rules {
+            name: name.to_owned(),
+            path: path.as_ref().to_owned(),
+            out_dir: None,
+            kind: DfaKind::Dense,
+            anchor: Anchor::Unanchored,
+            case_insensitive: false,
+        }
+    }
+
+    /// Set the directory to write to. Defaults to the `OUT_DIR` of the build script.
+    pub fn out_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.out_dir = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Set the representation of the embedded DFA. Defaults to `DfaKind::Dense`.
+    pub fn dfa_kind(mut self, kind: DfaKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Set where every regular expression must match within a key, see `RegexMapBuilder::anchor`.
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Set the value for the case insensitive (`i`) flag of every regular expression.
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Compile the rules and write `<name>.regexmap` and the Rust source `include_regex_map!` includes
+    /// to the output directory.
+    ///
+    /// When run from a build script, this also tells Cargo to rerun it when the rules file changes.
+    pub fn write(self) -> Result<(), RulesError> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => std::env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or(RulesError::OutDir)?,
+        };
+        if std::env::var_os("CARGO").is_some() {
+            println!("cargo:rerun-if-changed={}", self.path.display());
+        }
+
+        let source = fs::read_to_string(&self.path).map_err(io_error(&self.path))?;
+        let mut names = Vec::new();
+        let mut lines = Vec::new();
+        let mut entries = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            // Only the whitespace before and after the name is dropped, so a regular expression may end in
+            // spaces.
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let pattern = line
+                .split_once(char::is_whitespace)
+                .map(|(name, pattern)| (name, pattern.trim_start()))
+                .filter(|(_, pattern)| !pattern.is_empty());
+            match pattern {
+                Some((name, pattern)) => {
+                    names.push(name.to_owned());
+                    lines.push(number + 1);
+                    entries.push((pattern.to_owned(), ()));
+                }
+                None => {
+                    return Err(RulesError::Syntax {
+                        path: self.path.clone(),
+                        line: number + 1,
+                    })
+                }
+            }
+        }
+
+        let map = RegexMapBuilder::new(entries)
+            .anchor(self.anchor)
+            .case_insensitive(self.case_insensitive)
+            .build()
+            .map_err(|error| RulesError::Pattern {
+                path: self.path.clone(),
+                line: error.index().map(|index| lines[index]),
+                error,
+            })?;
+        let bytes = map.to_bytes(self.kind).map_err(RulesError::Dfa)?;
+
+        let blob = out_dir.join(format!("{}.regexmap", self.name));
+        fs::write(&blob, bytes).map_err(io_error(&blob))?;
+        let source = format!(
+            "{{\n    \
+                static MAP: ::std::sync::LazyLock<::regex_map::RegexMap<&'static str>> =\n        \
+                    ::std::sync::LazyLock::new(|| {{\n            \
+                        ::regex_map::RegexMap::from_bytes(include_bytes!({:?}), {:?})\n                \
+                            .expect(\"the embedded regex map should load\")\n        \
+                    }});\n    \
+                ::std::sync::LazyLock::force(&MAP)\n\
+            }}\n",
+            blob.display().to_string(),
+            names,
+        );
+        let include = out_dir.join(format!("{}.regexmap.rs", self.name));
+        fs::write(&include, source).map_err(io_error(&include))
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> RulesError + '_ {
+    move |error| RulesError::Io {
+        path: path.to_owned(),
+        error,
+    }
+}
+
+/// Error returned when a rules file could not be compiled by `Rules::write`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RulesError {
+    /// The rules file could not be read, or the output could not be written.
+    Io {
+        /// The file that could not be read or written.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// A line of the rules file has a name but no regular expression.
+    Syntax {
+        /// The rules file.
+        path: PathBuf,
+        /// The offending line, starting from 1.
+        line: usize,
+    },
+    /// A regular expression of the rules file is invalid.
+    Pattern {
+        /// The rules file.
+        path: PathBuf,
+        /// The offending line, starting from 1, if the error can be attributed to a single rule.
+        line: Option<usize>,
+        /// The underlying error.
+        error: Error,
+    },
+    /// The DFA could not be built.
+    Dfa(DfaError),
+    /// No output directory was set and `OUT_DIR` is not set, i.e. not running from a build script.
+    OutDir,
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            RulesError::Syntax { path, line } => write!(
+                f,
+                "{}:{}: expected a name followed by a regular expression",
+                path.display(),
+                line
+            ),
+            RulesError::Pattern {
+                path,
+                line: Some(line),
+                error,
+            } => write!(f, "{}:{}: {}", path.display(), line, error),
+            RulesError::Pattern {
+                path,
+                line: None,
+                error,
+            } => write!(f, "{}: {}", path.display(), error),
+            RulesError::Dfa(error) => error.fmt(f),
+            RulesError::OutDir => write!(f, "OUT_DIR is not set"),
+        }
+    }
+}
+
+impl std::error::Error for RulesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RulesError::Io { error, .. } => Some(error),
+            RulesError::Pattern { error, .. } => Some(error),
+            RulesError::Dfa(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Embed a map compiled by `regex_map::build::Rules` in the build script, as a
+/// `&'static RegexMap<&'static str>` whose values are the names of the rules.
+///
+/// The argument is the name given to `Rules::new`. The map is loaded on first use by each invocation of
+/// the macro, so it is best wrapped in a function. See the `build` module for an example.
+#[macro_export]
+macro_rules! include_regex_map {
+    ($name:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $name, ".regexmap.rs"))
+    };
+}
@@ -0,0 +1,423 @@
+//! Precompiled maps, enabled by the `dfa` feature.
+//!
+//! `RegexMap::to_bytes` compiles the regular expressions of a map into a single `regex-automata` DFA and
+//! serializes it together with the patterns. `RegexMap::from_bytes` loads such a blob without compiling
+//! anything, which makes it suitable for maps too large to build at startup.
+//!
+//! ```
+//! use regex_map::{DfaKind, RegexMap};
+//!
+//! let map = RegexMap::new([
+//!    ("^/api/", 1),
+//!    ("^/api/users/[0-9]+$", 2),
+//! ]);
+//! let bytes = map.to_bytes(DfaKind::Dense).unwrap();
+//!
+//! let map = RegexMap::from_bytes(&bytes, ["api", "user"]).unwrap();
+//! assert_eq!(map.get("/api/users/42").cloned().collect::<Vec<_>>(), vec!["api", "user"]);
+//! ```
+//!
+//! The values are not part of the blob and are passed to `RegexMap::from_bytes` in entry order. A blob
+//! written by another version of this crate, on a machine of different endianness, or for the other
+//! kind of key is rejected:
+//!
+//! ```
+//! use regex_map::{DfaError, DfaKind, RegexMap};
+//!
+//! let bytes = RegexMap::new([("foo", 1)]).to_bytes(DfaKind::Sparse).unwrap();
+//!
+//! let error = regex_map::bytes::RegexMap::<i32>::from_bytes(&bytes, [1]).unwrap_err();
+//! assert!(matches!(error, DfaError::Haystack));
+//! ```
+//!
+//! A loaded map supports every operation of a compiled one, with the options it was built with. Editing
+//! it drops the DFA, and the next lookup compiles the map as usual:
+//!
+//! ```
+//! use regex_map::{Anchor, DfaKind, RegexMap, RegexMapBuilder};
+//!
+//! let map = RegexMapBuilder::new([("foo", 1)]).anchor(Anchor::Full).build().unwrap();
+//! let bytes = map.to_bytes(DfaKind::Dense).unwrap();
+//!
+//! let mut map = RegexMap::from_bytes(&bytes, [1]).unwrap();
+//! map.insert("bar", 2).unwrap();
+//! assert_eq!(map.get("xbarx").count(), 0);
+//! assert_eq!(map.get("bar").cloned().collect::<Vec<_>>(), vec![2]);
+//! ```
+
+use std::fmt;
+
+use regex_automata::dfa::{dense, sparse, Automaton};
+use regex_automata::nfa::thompson;
+use regex_automata::{Input, MatchKind, PatternSet};
+
+use crate::haystack::Haystack;
+use crate::map::RegexMap;
+use crate::matcher::Matcher;
+use crate::options::Options;
+use crate::pattern::Anchor;
+
+/// The representation of the DFA written by `RegexMap::to_bytes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DfaKind {
+    /// A dense DFA, which is the fastest to search but may take a lot of space.
+    Dense,
+    /// A sparse DFA, which is smaller but slower to search.
+    Sparse,
+}
+
+/// Error returned when a precompiled map could not be written or loaded.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DfaError {
+    /// The DFA could not be built, e.g. because a regular expression uses a Unicode word boundary or
+    /// the DFA exceeds the size limit.
+    Build(Box<dense::BuildError>),
+    /// The bytes do not start with the header of a precompiled map.
+    Magic,
+    /// The blob was written by an incompatible version of this crate.
+    Version(u32),
+    /// The blob was written on a machine of different endianness.
+    Endianness,
+    /// The blob was written for the other kind of key, `str` or `[u8]`.
+    Haystack,
+    /// The blob is truncated or otherwise malformed.
+    Corrupt,
+    /// The DFA of the blob is invalid.
+    Deserialize(regex_automata::util::wire::DeserializeError),
+    /// The number of values does not match the number of patterns of the blob.
+    Values {
+        /// The number of patterns of the blob.
+        expected: usize,
+        /// The number of values given.
+        found: usize,
+    },
+}
+
+impl fmt::Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DfaError::Build(error) => write!(f, "could not build the DFA: {}", error),
+            DfaError::Magic => write!(f, "not a precompiled regex map"),
+            DfaError::Version(version) => write!(
+                f,
+                "unsupported precompiled regex map version {} (expected {})",
+                version, VERSION
+            ),
+            DfaError::Endianness => write!(f, "precompiled regex map has the wrong endianness"),
+            DfaError::Haystack => write!(f, "precompiled regex map is for the other kind of key"),
+            DfaError::Corrupt => write!(f, "precompiled regex map is corrupt"),
+            DfaError::Deserialize(error) => write!(f, "invalid DFA: {}", error),
+            DfaError::Values { expected, found } => write!(
+                f,
+                "precompiled regex map has {} patterns but {} values were given",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DfaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DfaError::Build(error) => Some(&**error),
+            DfaError::Deserialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+const MAGIC: &[u8; 8] = b"REGEXMAP";
+
+/// Bumped whenever the layout of the blob changes.
+const VERSION: u32 = 2;
+
+/// Written in native byte order, so that reading it back on a machine of the other endianness fails.
+const ENDIANNESS: u32 = 0x0102_0304;
+
+/// The DFA a map loaded with `RegexMap::from_bytes` is searched with.
+#[derive(Clone)]
+pub(crate) enum Dfa {
+    Dense(dense::DFA<Vec<u32>>),
+    Sparse(sparse::DFA<Vec<u8>>),
+}
+
+impl Dfa {
+    fn build(
+        exprs: &[String],
+        options: &Options,
+        utf8: bool,
+        kind: DfaKind,
+    ) -> Result<Self, DfaError> {
+        let dense = dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .match_kind(MatchKind::All)
+                    .dfa_size_limit(options.dfa_size_limit)
+                    .determinize_size_limit(options.dfa_size_limit),
+            )
+            .syntax(options.syntax_config(utf8))
+            .thompson(
+                thompson::Config::new()
+                    .utf8(utf8)
+                    .nfa_size_limit(options.size_limit),
+            )
+            .build_many(exprs)
+            .map_err(|error| DfaError::Build(Box::new(error)))?;
+        match kind {
+            DfaKind::Dense => Ok(Dfa::Dense(dense)),
+            DfaKind::Sparse => Ok(Dfa::Sparse(
+                dense
+                    .to_sparse()
+                    .map_err(|error| DfaError::Build(Box::new(error)))?,
+            )),
+        }
+    }
+
+    /// The indices of the patterns matching anywhere in `key`, in ascending order.
+    pub(crate) fn which(&self, key: &[u8]) -> Vec<usize> {
+        let input = Input::new(key);
+        let mut patterns = PatternSet::new(self.pattern_len());
+        match self {
+            Dfa::Dense(dfa) => dfa.try_which_overlapping_matches(&input, &mut patterns),
+            Dfa::Sparse(dfa) => dfa.try_which_overlapping_matches(&input, &mut patterns),
+        }
+        .expect("a DFA without quit bytes should not fail to search");
+        patterns.iter().map(|pattern| pattern.as_usize()).collect()
+    }
+
+    fn pattern_len(&self) -> usize {
+        match self {
+            Dfa::Dense(dfa) => dfa.pattern_len(),
+            Dfa::Sparse(dfa) => dfa.pattern_len(),
+        }
+    }
+}
+
+impl<V, H: ?Sized + Haystack> RegexMap<V, H> {
+    /// Compile the map into a single DFA and serialize it, together with the patterns, priorities and
+    /// options of the map, for loading with `RegexMap::from_bytes`.
+    ///
+    /// Building a DFA can take much longer and much more memory than building the map itself, so this is
+    /// meant to be done ahead of time, e.g. in a build script. Both are bounded by
+    /// `RegexMapBuilder::dfa_size_limit` when it is set, and unbounded otherwise:
+    ///
+    /// ```
+    /// use regex_map::{DfaError, DfaKind, RegexMapBuilder};
+    ///
+    /// let map = RegexMapBuilder::new([(r"\w{20}", 1)]).dfa_size_limit(1 << 16).build().unwrap();
+    /// assert!(matches!(map.to_bytes(DfaKind::Dense), Err(DfaError::Build(_))));
+    /// ```
+    pub fn to_bytes(&self, kind: DfaKind) -> Result<Vec<u8>, DfaError> {
+        let exprs = self.patterns().map(str::to_owned).collect::<Vec<_>>();
+        let dfa = Dfa::build(&exprs, self.options(), H::UTF8, kind)?;
+
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.u32(ENDIANNESS);
+        writer.u32(VERSION);
+        writer.u32(u32::from(H::UTF8));
+        writer.u32(match kind {
+            DfaKind::Dense => 0,
+            DfaKind::Sparse => 1,
+        });
+        write_options(&mut writer, self.options());
+        writer.u64(exprs.len() as u64);
+        for (expr, &priority) in exprs.iter().zip(self.priorities()) {
+            writer.u32(priority as u32);
+            writer.bytes(expr.as_bytes());
+        }
+        match &dfa {
+            Dfa::Dense(dfa) => {
+                let (bytes, padding) = dfa.to_bytes_native_endian();
+                writer.bytes(&bytes[padding..]);
+            }
+            Dfa::Sparse(dfa) => writer.bytes(&dfa.to_bytes_native_endian()),
+        }
+        Ok(writer.0)
+    }
+
+    /// Load a map written by `RegexMap::to_bytes`, with the given values in entry order.
+    ///
+    /// The DFA is validated but not compiled again, so this takes time proportional to the size of the
+    /// blob.
+    pub fn from_bytes<I>(bytes: &[u8], values: I) -> Result<Self, DfaError>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(DfaError::Magic);
+        }
+        if reader.u32()? != ENDIANNESS {
+            return Err(DfaError::Endianness);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(DfaError::Version(version));
+        }
+        if reader.u32()? != u32::from(H::UTF8) {
+            return Err(DfaError::Haystack);
+        }
+        let kind = match reader.u32()? {
+            0 => DfaKind::Dense,
+            1 => DfaKind::Sparse,
+            _ => return Err(DfaError::Corrupt),
+        };
+        let options = read_options(&mut reader)?;
+        let len = usize::try_from(reader.u64()?).map_err(|_| DfaError::Corrupt)?;
+        let mut exprs = Vec::new();
+        let mut priorities = Vec::new();
+        for _ in 0..len {
+            priorities.push(reader.u32()? as i32);
+            let expr = reader.bytes()?.to_vec();
+            exprs.push(String::from_utf8(expr).map_err(|_| DfaError::Corrupt)?);
+        }
+        let dfa = match kind {
+            DfaKind::Dense => {
+                // The transition table is read in place as `u32`s, so it must be aligned.
+                let bytes = reader.bytes()?;
+                let mut buffer = vec![0; bytes.len() + 3];
+                let offset = buffer.as_ptr().align_offset(4);
+                let aligned = &mut buffer[offset..offset + bytes.len()];
+                aligned.copy_from_slice(bytes);
+                let (dfa, _) = dense::DFA::from_bytes(aligned).map_err(DfaError::Deserialize)?;
+                Dfa::Dense(dfa.to_owned())
+            }
+            DfaKind::Sparse => {
+                let (dfa, _) =
+                    sparse::DFA::from_bytes(reader.bytes()?).map_err(DfaError::Deserialize)?;
+                Dfa::Sparse(dfa.to_owned())
+            }
+        };
+        if dfa.pattern_len() != len || !reader.0.is_empty() {
+            return Err(DfaError::Corrupt);
+        }
+
+        let values = values.into_iter().collect::<Vec<_>>();
+        if values.len() != len {
+            return Err(DfaError::Values {
+                expected: len,
+                found: values.len(),
+            });
+        }
+        let matcher = Matcher::from_dfa(&exprs, &options, dfa);
+        Ok(RegexMap::from_parts(
+            exprs, values, priorities, options, matcher,
+        ))
+    }
+}
+
+fn write_options(writer: &mut Writer, options: &Options) {
+    let flags = [
+        options.case_insensitive,
+        options.multi_line,
+        options.dot_matches_new_line,
+        options.unicode,
+    ];
+    writer.u32(
+        flags
+            .iter()
+            .enumerate()
+            .map(|(i, &flag)| u32::from(flag) << i)
+            .sum(),
+    );
+    for limit in [
+        options.size_limit,
+        options.dfa_size_limit,
+        options.shard_len,
+    ] {
+        writer.u64(limit.map_or(u64::MAX, |limit| limit as u64));
+    }
+    writer.u32(match options.anchor {
+        Anchor::Unanchored => 0,
+        Anchor::Prefix => 1,
+        Anchor::Full => 2,
+    });
+    writer.u32(match options.prefilter {
+        None => 0,
+        Some(false) => 1,
+        Some(true) => 2,
+    });
+}
+
+fn read_options(reader: &mut Reader<'_>) -> Result<Options, DfaError> {
+    let flags = reader.u32()?;
+    let mut limit = || -> Result<Option<usize>, DfaError> {
+        match reader.u64()? {
+            u64::MAX => Ok(None),
+            limit => Ok(Some(usize::try_from(limit).map_err(|_| DfaError::Corrupt)?)),
+        }
+    };
+    let size_limit = limit()?;
+    let dfa_size_limit = limit()?;
+    let shard_len = limit()?;
+    let anchor = match reader.u32()? {
+        0 => Anchor::Unanchored,
+        1 => Anchor::Prefix,
+        2 => Anchor::Full,
+        _ => return Err(DfaError::Corrupt),
+    };
+    let prefilter = match reader.u32()? {
+        0 => None,
+        1 => Some(false),
+        2 => Some(true),
+        _ => return Err(DfaError::Corrupt),
+    };
+    Ok(Options {
+        case_insensitive: flags & 1 != 0,
+        multi_line: flags & 2 != 0,
+        dot_matches_new_line: flags & 4 != 0,
+        unicode: flags & 8 != 0,
+        size_limit,
+        dfa_size_limit,
+        anchor,
+        shard_len,
+        prefilter,
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Write `bytes` prefixed with their length.
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DfaError> {
+        if self.0.len() < len {
+            return Err(DfaError::Corrupt);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DfaError> {
+        Ok(u32::from_ne_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DfaError> {
+        Ok(u64::from_ne_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Read bytes prefixed with their length.
+    fn bytes(&mut self) -> Result<&'a [u8], DfaError> {
+        let len = usize::try_from(self.u64()?).map_err(|_| DfaError::Corrupt)?;
+        self.take(len)
+    }
+}
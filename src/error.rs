@@ -0,0 +1,174 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::pattern::Pattern;
+
+/// Error returned when a `RegexMap` could not be built.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The regular expression of the entry at `index` is invalid.
+    Pattern {
+        /// Index of the offending entry, in the order the entries were given.
+        index: usize,
+        /// The offending regular expression.
+        pattern: String,
+        /// The underlying error reported by the `regex` crate.
+        error: regex::Error,
+    },
+    /// Every regular expression is valid on its own, but a set of them could not be built.
+    Set(regex::Error),
+}
+
+impl Error {
+    /// Index of the offending entry, if the error can be attributed to a single entry.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Error::Pattern { index, .. } => Some(*index),
+            Error::Set(_) => None,
+        }
+    }
+
+    /// The offending regular expression, if the error can be attributed to a single entry.
+    pub fn pattern(&self) -> Option<&str> {
+        match self {
+            Error::Pattern { pattern, .. } => Some(pattern),
+            Error::Set(_) => None,
+        }
+    }
+
+    /// The underlying error reported by the `regex` crate.
+    pub fn regex_error(&self) -> &regex::Error {
+        match self {
+            Error::Pattern { error, .. } => error,
+            Error::Set(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Pattern {
+                index,
+                pattern,
+                error,
+            } => {
+                write!(
+                    f,
+                    "invalid regular expression #{} `{}`: {}",
+                    index, pattern, error
+                )
+            }
+            Error::Set(error) => write!(f, "could not build the regular expression set: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.regex_error())
+    }
+}
+
+/// Attribute an error returned while building a whole set to the first entry that fails to compile on
+/// its own, falling back to `Error::Set` when every entry compiles.
+pub(crate) fn locate<S, F>(exprs: &[S], error: regex::Error, compile: F) -> Error
+where
+    S: AsRef<str>,
+    F: Fn(&str) -> Result<(), regex::Error>,
+{
+    for (index, expr) in exprs.iter().enumerate() {
+        if let Err(error) = compile(expr.as_ref()) {
+            return Error::Pattern {
+                index,
+                pattern: expr.as_ref().to_owned(),
+                error,
+            };
+        }
+    }
+    Error::Set(error)
+}
+
+/// Report returned by `RegexMap::validate`, listing every invalid regular expression.
+#[derive(Clone, Debug)]
+pub struct ValidationReport {
+    /// The invalid entries, in the order they were given.
+    pub errors: Vec<InvalidPattern>,
+}
+
+/// An invalid regular expression found by `RegexMap::validate`.
+#[derive(Clone, Debug)]
+pub struct InvalidPattern {
+    /// Index of the offending entry, in the order the entries were given.
+    pub index: usize,
+    /// The offending regular expression.
+    pub pattern: String,
+    /// Byte range of `pattern` the error points at.
+    pub span: Range<usize>,
+    /// Description of what is wrong with the regular expression.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid regular expression(s)", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+impl fmt::Display for InvalidPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} `{}` at {}..{}: {}",
+            self.index, self.pattern, self.span.start, self.span.end, self.message
+        )
+    }
+}
+
+/// Parse each expression on its own and collect every one that fails.
+///
+/// `utf8` mirrors the difference between the `str` and `bytes` flavours of the `regex` crate: when
+/// set, expressions that may match invalid UTF-8 are rejected.
+pub(crate) fn validate<I, S>(exprs: I, utf8: bool) -> Result<(), ValidationReport>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<Pattern>,
+{
+    let mut errors = Vec::new();
+    for (index, expr) in exprs.into_iter().enumerate() {
+        let pattern = expr.into();
+        let expr = pattern.as_str();
+        if let Some(error) = pattern.syntax_error(utf8) {
+            let (span, message) = match &error {
+                regex_syntax::Error::Parse(error) => (span(error.span()), error.kind().to_string()),
+                regex_syntax::Error::Translate(error) => {
+                    (span(error.span()), error.kind().to_string())
+                }
+                _ => (0..expr.len(), error.to_string()),
+            };
+            errors.push(InvalidPattern {
+                index,
+                pattern: expr.to_owned(),
+                span,
+                message,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationReport { errors })
+    }
+}
+
+fn span(span: &regex_syntax::ast::Span) -> Range<usize> {
+    span.start.offset..span.end.offset
+}
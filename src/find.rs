@@ -0,0 +1,446 @@
+use std::fmt;
+use std::ops::Range;
+
+use regex_automata::hybrid::dfa::{Cache, OverlappingState, DFA};
+use regex_automata::meta::{self, Regex};
+use regex_automata::nfa::thompson;
+use regex_automata::{Anchored, HalfMatch, Input, Match, PatternID};
+use regex_syntax::hir::{Capture, Hir, HirKind, Repetition};
+
+use crate::options::Options;
+
+/// Which match `RegexMap::find_iter` reports when several start at the leftmost position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MatchKind {
+    /// The match preferred by the regular expression of the entry inserted first, as `regex::Regex`
+    /// would with the regular expressions joined by `|` in insertion order. This is the default.
+    #[default]
+    LeftmostFirst,
+    /// The longest match of any regular expression, ties broken in favour of the entry inserted first.
+    LeftmostLongest,
+}
+
+/// The multi-pattern regular expressions `RegexMap::find_iter` searches with, built on first use.
+///
+/// Like the sets of a `RegexMap`, the regular expressions are split across shards when they do not fit
+/// together under the size limit.
+#[derive(Clone)]
+pub(crate) struct Finder {
+    shards: Vec<FinderShard>,
+}
+
+#[derive(Clone)]
+struct FinderShard {
+    /// Index of the first regular expression of the shard.
+    offset: usize,
+    /// Finds the leftmost match, preferring earlier patterns.
+    first: Regex,
+    /// Finds the longest match, when searching anchored at the start of the leftmost match.
+    all: Regex,
+}
+
+impl Finder {
+    pub(crate) fn new(exprs: &[String], options: &Options, utf8: bool) -> Self {
+        let build = |range: Range<usize>, kind| {
+            let mut config = meta::Config::new()
+                .match_kind(kind)
+                .nfa_size_limit(options.size_limit)
+                .utf8_empty(utf8);
+            if let Some(limit) = options.dfa_size_limit {
+                config = config.hybrid_cache_capacity(limit);
+            }
+            Regex::builder()
+                .configure(config)
+                .syntax(options.syntax_config(utf8))
+                .build_many(&exprs[range])
+                .map_err(Box::new)
+        };
+        let shards = shard::<_, Box<meta::BuildError>>(
+            exprs.len(),
+            &|range: Range<usize>| {
+                Ok(FinderShard {
+                    offset: range.start,
+                    first: build(range.clone(), regex_automata::MatchKind::LeftmostFirst)?,
+                    all: build(range, regex_automata::MatchKind::All)?,
+                })
+            },
+            |error| error.size_limit().is_some(),
+        );
+        Finder { shards }
+    }
+
+    /// Find the first match in `input`, in the span `input` is restricted to.
+    pub(crate) fn find(&self, input: &Input<'_>, kind: MatchKind) -> Option<Match> {
+        let mut best: Option<Match> = None;
+        for shard in &self.shards {
+            let Some(m) = shard.find(input, kind) else {
+                continue;
+            };
+            // Shards are searched in order, so on a tie the earlier entry is kept.
+            let better = best.is_none_or(|best| match kind {
+                MatchKind::LeftmostFirst => m.start() < best.start(),
+                MatchKind::LeftmostLongest => {
+                    m.start() < best.start() || (m.start() == best.start() && m.end() > best.end())
+                }
+            });
+            if better {
+                let pattern = PatternID::must(shard.offset + m.pattern().as_usize());
+                best = Some(Match::new(pattern, m.span()));
+            }
+        }
+        best
+    }
+}
+
+impl FinderShard {
+    fn find(&self, input: &Input<'_>, kind: MatchKind) -> Option<Match> {
+        let first = self.first.search(input)?;
+        match kind {
+            MatchKind::LeftmostFirst => Some(first),
+            MatchKind::LeftmostLongest => {
+                let input = input.clone().range(first.start()..).anchored(Anchored::Yes);
+                self.all
+                    .search(&input)
+                    .filter(|all| all.end() > first.end())
+                    .or(Some(first))
+            }
+        }
+    }
+}
+
+/// A regular expression whose anchored searches find the longest match of `expr`.
+pub(crate) fn longest(expr: &str, options: &Options, utf8: bool) -> Regex {
+    let config = meta::Config::new()
+        .match_kind(regex_automata::MatchKind::All)
+        .nfa_size_limit(options.size_limit)
+        .utf8_empty(utf8);
+    Regex::builder()
+        .configure(config)
+        .syntax(options.syntax_config(utf8))
+        .build(expr)
+        .expect("regular expressions accepted by the set should compile")
+}
+
+/// Build automata for consecutive ranges of `len` regular expressions with `build`, starting from a
+/// single range and halving any that exceeds the size limit, like `Shards::build`.
+fn shard<T, E: fmt::Debug>(
+    len: usize,
+    build: &impl Fn(Range<usize>) -> Result<T, E>,
+    too_big: impl Fn(&E) -> bool + Copy,
+) -> Vec<T> {
+    fn push<T, E: fmt::Debug>(
+        shards: &mut Vec<T>,
+        range: Range<usize>,
+        build: &impl Fn(Range<usize>) -> Result<T, E>,
+        too_big: impl Fn(&E) -> bool + Copy,
+    ) {
+        match build(range.clone()) {
+            Ok(shard) => shards.push(shard),
+            Err(error) if too_big(&error) && range.len() > 1 => {
+                let mid = range.start + range.len() / 2;
+                push(shards, range.start..mid, build, too_big);
+                push(shards, mid..range.end, build, too_big);
+            }
+            Err(error) => panic!(
+                "regular expressions accepted by the set should compile: {:?}",
+                error
+            ),
+        }
+    }
+
+    let mut shards = Vec::new();
+    if len > 0 {
+        push(&mut shards, 0..len, build, too_big);
+    }
+    shards
+}
+
+/// The lazy DFAs `RegexMap::find_overlapping_iter` and `Tokenizer` search with, built on first use.
+///
+/// Lazy DFAs cannot match Unicode word boundaries on non-ASCII text, so they are built with those
+/// assertions removed, which can only add matches, and the matches of the entries that had any are
+/// confirmed by the regular expression of the entry alone. Like `Finder`, the DFAs are split across
+/// shards when they do not fit together under the size limit.
+#[derive(Clone)]
+pub(crate) struct OverlappingFinder {
+    shards: Vec<OverlappingShard>,
+    /// The regular expression of each entry with a Unicode word boundary, finding the longest match.
+    exact: Vec<Option<Regex>>,
+    utf8: bool,
+}
+
+#[derive(Clone)]
+struct OverlappingShard {
+    /// Index of the first regular expression of the shard.
+    offset: usize,
+    /// Reports the end of every match of every pattern.
+    forward: DFA,
+    /// Finds the start of a match from its end, searching backwards anchored on its pattern.
+    reverse: DFA,
+}
+
+const QUIT: &str = "lazy DFAs without Unicode word boundaries should not quit";
+
+impl OverlappingFinder {
+    pub(crate) fn new(exprs: &[String], options: &Options, utf8: bool) -> Self {
+        let hirs = exprs
+            .iter()
+            .map(|expr| {
+                options
+                    .parser(utf8)
+                    .parse(expr)
+                    .expect("regular expressions accepted by the set should parse")
+            })
+            .collect::<Vec<_>>();
+        let exact = exprs
+            .iter()
+            .zip(&hirs)
+            .map(|(expr, hir)| {
+                hir.properties()
+                    .look_set()
+                    .contains_word_unicode()
+                    .then(|| longest(expr, options, utf8))
+            })
+            .collect();
+        let hirs = hirs.into_iter().map(relax).collect::<Vec<_>>();
+
+        let nfa = |range: Range<usize>, reverse| {
+            thompson::Compiler::new()
+                .configure(
+                    thompson::Config::new()
+                        .reverse(reverse)
+                        .which_captures(thompson::WhichCaptures::None)
+                        .nfa_size_limit(options.size_limit),
+                )
+                .build_many_from_hir(&hirs[range])
+                .map_err(Box::new)
+        };
+        let dfa = |nfa, reverse| {
+            let mut config = DFA::config()
+                .match_kind(regex_automata::MatchKind::All)
+                .starts_for_each_pattern(reverse)
+                .skip_cache_capacity_check(true);
+            if let Some(limit) = options.dfa_size_limit {
+                config = config.cache_capacity(limit);
+            }
+            DFA::builder()
+                .configure(config)
+                .build_from_nfa(nfa)
+                .expect("lazy DFAs without Unicode word boundaries should build")
+        };
+        let shards = shard::<_, Box<thompson::BuildError>>(
+            exprs.len(),
+            &|range: Range<usize>| {
+                Ok(OverlappingShard {
+                    offset: range.start,
+                    forward: dfa(nfa(range.clone(), false)?, false),
+                    reverse: dfa(nfa(range, true)?, true),
+                })
+            },
+            |error| error.size_limit().is_some(),
+        );
+        OverlappingFinder {
+            shards,
+            exact,
+            utf8,
+        }
+    }
+
+    pub(crate) fn create_caches(&self) -> Vec<Cache> {
+        self.shards
+            .iter()
+            .map(|shard| shard.forward.create_cache())
+            .collect()
+    }
+
+    /// Find the longest non-empty match starting at the start of `input`, as its pattern and end, ties
+    /// broken in favour of the pattern with the greatest `rank`.
+    pub(crate) fn longest_at<K: Ord>(
+        &self,
+        caches: &mut [Cache],
+        input: &Input<'_>,
+        rank: impl Fn(usize) -> K,
+    ) -> Option<(usize, usize)> {
+        let input = input.clone().anchored(Anchored::Yes);
+        let mut longest = None;
+        for (shard, cache) in self.shards.iter().zip(caches) {
+            let mut state = OverlappingState::start();
+            loop {
+                shard
+                    .forward
+                    .try_search_overlapping_fwd(cache, &input, &mut state)
+                    .expect(QUIT);
+                let Some(m) = state.get_match() else {
+                    break;
+                };
+                let index = shard.offset + m.pattern().as_usize();
+                let candidate = (m.offset(), rank(index), index);
+                if m.offset() > input.start()
+                    && longest
+                        .as_ref()
+                        .is_none_or(|(end, rank, _)| (candidate.0, &candidate.1) > (*end, rank))
+                    && self.is_match_between(index, input.haystack(), input.start(), m.offset())
+                {
+                    longest = Some(candidate);
+                }
+            }
+        }
+        longest.map(|(end, _, index)| (index, end))
+    }
+
+    /// The leftmost position from `start` where a match of the entry at `index` ending at `end` starts.
+    fn confirm(&self, index: usize, haystack: &[u8], start: usize, end: usize) -> Option<usize> {
+        if self.exact[index].is_none() {
+            return Some(start);
+        }
+        let input = Input::new(haystack);
+        (start..=end)
+            .filter(|&at| !self.utf8 || input.is_char_boundary(at))
+            .find(|&at| self.is_match_between(index, haystack, at, end))
+    }
+
+    /// Whether the entry at `index` matches exactly `start..end`, for a match found by the DFAs.
+    fn is_match_between(&self, index: usize, haystack: &[u8], start: usize, end: usize) -> bool {
+        let Some(regex) = &self.exact[index] else {
+            return true;
+        };
+        let input = Input::new(haystack)
+            .range(start..end)
+            .anchored(Anchored::Yes);
+        regex.search_half(&input).is_some_and(|m| m.offset() == end)
+    }
+}
+
+/// Remove the Unicode word boundaries of `hir`, so that it matches a superset of the original a lazy
+/// DFA can search for.
+fn relax(hir: Hir) -> Hir {
+    if !hir.properties().look_set().contains_word_unicode() {
+        return hir;
+    }
+    match hir.into_kind() {
+        HirKind::Look(_) => Hir::empty(),
+        HirKind::Repetition(repetition) => Hir::repetition(Repetition {
+            sub: Box::new(relax(*repetition.sub)),
+            ..repetition
+        }),
+        HirKind::Capture(capture) => Hir::capture(Capture {
+            sub: Box::new(relax(*capture.sub)),
+            ..capture
+        }),
+        HirKind::Concat(subs) => Hir::concat(subs.into_iter().map(relax).collect()),
+        HirKind::Alternation(subs) => Hir::alternation(subs.into_iter().map(relax).collect()),
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) => {
+            unreachable!("only look-around assertions and their parents contain word boundaries")
+        }
+    }
+}
+
+/// Iterator over every match of every regular expression of a `RegexMap`, overlapping or not, created
+/// by `RegexMap::find_overlapping_iter`.
+///
+/// The search states and the caches of the automata are created with the iterator and reused for every
+/// match, so advancing it does not allocate, other than to grow the caches.
+pub struct Overlapping<'a, V> {
+    finder: &'a OverlappingFinder,
+    values: &'a [V],
+    input: Input<'a>,
+    /// The search of each shard of `finder`, in order.
+    searches: Vec<ShardSearch>,
+}
+
+struct ShardSearch {
+    state: OverlappingState,
+    forward: Cache,
+    reverse: Cache,
+    /// The next end found by the forward DFA, not reported yet.
+    pending: Option<HalfMatch>,
+    done: bool,
+}
+
+impl<'a, V> Overlapping<'a, V> {
+    pub(crate) fn new(finder: &'a OverlappingFinder, values: &'a [V], haystack: &'a [u8]) -> Self {
+        Overlapping {
+            finder,
+            values,
+            input: Input::new(haystack),
+            searches: finder
+                .shards
+                .iter()
+                .map(|shard| ShardSearch {
+                    state: OverlappingState::start(),
+                    forward: shard.forward.create_cache(),
+                    reverse: shard.reverse.create_cache(),
+                    pending: None,
+                    done: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a, V> Iterator for Overlapping<'a, V> {
+    type Item = (usize, Range<usize>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Report the earliest end of any shard, the earlier shard on a tie.
+            let mut next: Option<(usize, usize)> = None;
+            for (i, (shard, search)) in self
+                .finder
+                .shards
+                .iter()
+                .zip(&mut self.searches)
+                .enumerate()
+            {
+                if search.pending.is_none() && !search.done {
+                    shard
+                        .forward
+                        .try_search_overlapping_fwd(
+                            &mut search.forward,
+                            &self.input,
+                            &mut search.state,
+                        )
+                        .expect(QUIT);
+                    search.pending = search.state.get_match();
+                    search.done = search.pending.is_none();
+                }
+                if let Some(end) = search.pending {
+                    if next.is_none_or(|(_, next)| end.offset() < next) {
+                        next = Some((i, end.offset()));
+                    }
+                }
+            }
+            let (i, end) = next?;
+            let (shard, search) = (&self.finder.shards[i], &mut self.searches[i]);
+            let pattern = search
+                .pending
+                .take()
+                .expect("the shard has a pending match")
+                .pattern();
+
+            let input = self
+                .input
+                .clone()
+                .range(..end)
+                .anchored(Anchored::Pattern(pattern));
+            let start = shard
+                .reverse
+                .try_search_rev(&mut search.reverse, &input)
+                .expect(QUIT)
+                .expect("a match found forwards should be found backwards")
+                .offset();
+            let index = shard.offset + pattern.as_usize();
+            let Some(start) = self
+                .finder
+                .confirm(index, self.input.haystack(), start, end)
+            else {
+                continue;
+            };
+            // Like `regex::Regex`, never split a UTF-8 encoded character with an empty match.
+            if self.finder.utf8 && start == end && !self.input.is_char_boundary(end) {
+                continue;
+            }
+            return Some((index, start..end, &self.values[index]));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::ops::Range;
+
+use crate::options::{configure, Options};
+
+/// The type of key a `RegexMap` is queried with: `str` for `regex_map::RegexMap` and `[u8]` for
+/// `regex_map::bytes::RegexMap`.
+///
+/// It ties the map to the matching flavour of the `regex` crate, so that `RegexMap` is written once for
+/// both. This trait is sealed and cannot be implemented outside of this crate.
+pub trait Haystack: private::Sealed + ToOwned<Owned: Default> {
+    /// The set of regular expressions, `regex::RegexSet` or `regex::bytes::RegexSet`.
+    type Set: Clone;
+    /// A single regular expression, `regex::Regex` or `regex::bytes::Regex`.
+    type Regex: Clone;
+    /// The captures of a single regular expression, `regex::Captures` or `regex::bytes::Captures`.
+    type Captures<'h>
+    where
+        Self: 'h;
+    /// The indices matched by the set, `regex::SetMatches` or `regex::bytes::SetMatches`.
+    type SetMatches: SetMatches;
+
+    /// Whether regular expressions are restricted to matching valid UTF-8.
+    #[doc(hidden)]
+    const UTF8: bool;
+
+    #[doc(hidden)]
+    fn build_set(exprs: &[String], options: &Options) -> Result<Self::Set, regex::Error>;
+
+    #[doc(hidden)]
+    fn build_regex(expr: &str, options: &Options) -> Result<Self::Regex, regex::Error>;
+
+    #[doc(hidden)]
+    fn empty_set() -> Self::Set;
+
+    #[doc(hidden)]
+    fn matches(set: &Self::Set, key: &Self) -> Self::SetMatches;
+
+    #[doc(hidden)]
+    fn is_match(set: &Self::Set, key: &Self) -> bool;
+
+    #[doc(hidden)]
+    fn regex_is_match(regex: &Self::Regex, key: &Self) -> bool;
+
+    #[doc(hidden)]
+    fn captures<'h>(regex: &Self::Regex, key: &'h Self) -> Option<Self::Captures<'h>>;
+
+    #[doc(hidden)]
+    fn captures_at<'h>(
+        regex: &Self::Regex,
+        key: &'h Self,
+        start: usize,
+    ) -> Option<Self::Captures<'h>>;
+
+    #[doc(hidden)]
+    fn find(regex: &Self::Regex, key: &Self) -> Option<Range<usize>>;
+
+    /// Append the given range of `key` to `dst`.
+    #[doc(hidden)]
+    fn append(dst: &mut Self::Owned, key: &Self, range: Range<usize>);
+
+    #[doc(hidden)]
+    fn as_bytes(key: &Self) -> &[u8];
+}
+
+/// The common interface of `regex::SetMatches` and `regex::bytes::SetMatches`.
+pub trait SetMatches: private::Sealed {
+    /// Iterator over the matched indices, in ascending order.
+    type IntoIter: Iterator<Item = usize>;
+
+    /// Check if any regular expression matched.
+    fn matched_any(&self) -> bool;
+
+    /// Check if the regular expression at index `index` matched.
+    fn matched(&self, index: usize) -> bool;
+
+    /// Turn into an iterator over the matched indices, in ascending order.
+    fn into_indices(self) -> Self::IntoIter;
+}
+
+macro_rules! impl_haystack {
+    ($haystack:ty, $($regex:ident)::+, $utf8:expr, $append:ident) => {
+        impl private::Sealed for $haystack {}
+
+        impl Haystack for $haystack {
+            type Set = $($regex)::+::RegexSet;
+            type Regex = $($regex)::+::Regex;
+            type Captures<'h> = $($regex)::+::Captures<'h>;
+            type SetMatches = $($regex)::+::SetMatches;
+
+            const UTF8: bool = $utf8;
+
+            fn build_set(exprs: &[String], options: &Options) -> Result<Self::Set, regex::Error> {
+                configure!($($regex)::+::RegexSetBuilder::new(exprs), options).build()
+            }
+
+            fn build_regex(expr: &str, options: &Options) -> Result<Self::Regex, regex::Error> {
+                configure!($($regex)::+::RegexBuilder::new(expr), options).build()
+            }
+
+            fn empty_set() -> Self::Set {
+                $($regex)::+::RegexSet::empty()
+            }
+
+            fn matches(set: &Self::Set, key: &Self) -> Self::SetMatches {
+                set.matches(key)
+            }
+
+            fn is_match(set: &Self::Set, key: &Self) -> bool {
+                set.is_match(key)
+            }
+
+            fn regex_is_match(regex: &Self::Regex, key: &Self) -> bool {
+                regex.is_match(key)
+            }
+
+            fn captures<'h>(regex: &Self::Regex, key: &'h Self) -> Option<Self::Captures<'h>> {
+                regex.captures(key)
+            }
+
+            fn captures_at<'h>(
+                regex: &Self::Regex,
+                key: &'h Self,
+                start: usize,
+            ) -> Option<Self::Captures<'h>> {
+                regex.captures_at(key, start)
+            }
+
+            fn find(regex: &Self::Regex, key: &Self) -> Option<Range<usize>> {
+                regex.find(key).map(|m| m.range())
+            }
+
+            fn append(dst: &mut Self::Owned, key: &Self, range: Range<usize>) {
+                dst.$append(&key[range]);
+            }
+
+            fn as_bytes(key: &Self) -> &[u8] {
+                key.as_ref()
+            }
+        }
+
+        impl private::Sealed for $($regex)::+::SetMatches {}
+
+        impl SetMatches for $($regex)::+::SetMatches {
+            type IntoIter = $($regex)::+::SetMatchesIntoIter;
+
+            fn matched_any(&self) -> bool {
+                $($regex)::+::SetMatches::matched_any(self)
+            }
+
+            fn matched(&self, index: usize) -> bool {
+                $($regex)::+::SetMatches::matched(self, index)
+            }
+
+            fn into_indices(self) -> Self::IntoIter {
+                self.into_iter()
+            }
+        }
+    };
+}
+
+impl_haystack!(str, regex, true, push_str);
+impl_haystack!([u8], regex::bytes, false, extend_from_slice);
+
+mod private {
+    pub trait Sealed {}
+}
@@ -1,3 +1,35 @@
+#[cfg(feature = "dfa")]
+pub mod build;
 pub mod bytes;
+#[cfg(feature = "dfa")]
+mod dfa;
+mod error;
+mod find;
+mod haystack;
+mod map;
+mod matcher;
+mod options;
+mod pattern;
+mod regex_match;
+mod replace;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod shard;
+mod specificity;
+mod stats;
 mod string;
+mod tokenizer;
+#[cfg(feature = "dfa")]
+pub use dfa::{DfaError, DfaKind};
+pub use error::*;
+pub use find::{MatchKind, Overlapping};
+pub use haystack::*;
+pub use pattern::*;
+#[cfg(feature = "macros")]
+pub use regex_map_macros::{regex_map, RegexMatch};
+pub use regex_match::RegexMatch;
+pub use replace::Replacement;
+pub use specificity::{Entry, Specificity};
+pub use stats::Stats;
 pub use string::*;
+pub use tokenizer::{Token, TokenError, Tokens};
@@ -0,0 +1,1228 @@
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
+use std::sync::OnceLock;
+
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use regex_automata::meta;
+use regex_automata::util::iter::Searcher;
+use regex_automata::{Anchored, Input, Match};
+
+use crate::error::{self, Error, ValidationReport};
+use crate::find::{self, Finder, MatchKind, Overlapping, OverlappingFinder};
+use crate::haystack::Haystack;
+use crate::matcher::Matcher;
+use crate::options::Options;
+use crate::pattern::{Anchor, Pattern};
+use crate::replace::Replacement;
+use crate::specificity::{self, Entry, Specificity, Specifics};
+use crate::stats::Stats;
+
+/// Associative container where the keys are regular expressions, based on the `regex::RegexSet` data structure.
+///
+/// This is the implementation shared by `regex_map::RegexMap`, queried with `&str` keys, and
+/// `regex_map::bytes::RegexMap`, queried with `&[u8]` keys; the `H` parameter picks between the two.
+///
+/// Entries can be added and removed after construction. The underlying set is then rebuilt lazily, on
+/// the next lookup or on an explicit `RegexMap::compile`, so a batch of edits costs a single rebuild.
+pub struct RegexMap<V, H: ?Sized + Haystack> {
+    /// The regular expressions with their flags folded in, in insertion order.
+    exprs: Vec<String>,
+    values: Vec<V>,
+    priorities: Vec<i32>,
+    /// Whether every entry has the same priority, so that `get_first` can stop at the first matching set.
+    uniform: bool,
+    options: Options,
+    /// The set built from `exprs`, empty while the map is stale.
+    compiled: OnceLock<Compiled<H>>,
+}
+
+struct Compiled<H: ?Sized + Haystack> {
+    set: Matcher<H>,
+    finder: OnceLock<Finder>,
+    overlapping: OnceLock<OverlappingFinder>,
+    specifics: Vec<OnceLock<Specifics>>,
+    /// Per entry, the regular expression finding its longest match for `Specificity::LongestMatch`.
+    longest: Vec<OnceLock<meta::Regex>>,
+}
+
+impl<V, H: ?Sized + Haystack> RegexMap<V, H> {
+    /// Create a new `RegexMap` from iterator over (expression, value) pairs, where the expression is `&str`-like
+    /// or a `Pattern`.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    ("foo", 1),
+    ///    ("bar", 2),
+    ///    ("foobar", 3),
+    ///    ("^foo$", 4),
+    ///    ("^bar$", 5),
+    ///    ("^foobar$", 6),
+    /// ]);
+    ///
+    /// assert_eq!(map.get("foo").cloned().collect::<Vec<_>>(), vec![1, 4]);
+    /// assert_eq!(map.get("bar").cloned().collect::<Vec<_>>(), vec![2, 5], );
+    /// assert_eq!(map.get("foobar").cloned().collect::<Vec<_>>(), vec![1, 2, 3, 6]);
+    /// assert_eq!(map.get("XXX foo XXX").cloned().collect::<Vec<_>>(), vec![1]);
+    /// assert_eq!(map.get("XXX bar XXX").cloned().collect::<Vec<_>>(), vec![2]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the regular expressions is invalid, see `RegexMap::try_new` for a fallible version.
+    pub fn new<I, S>(items: I) -> Self
+    where
+        I: IntoIterator<Item = (S, V)>,
+        S: Into<Pattern>,
+    {
+        match Self::try_new(items) {
+            Ok(map) => map,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Create a new `RegexMap` from iterator over (expression, value) pairs, returning an error if any of
+    /// the regular expressions is invalid.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let error = RegexMap::try_new([
+    ///    ("foo", 1),
+    ///    ("(bar", 2),
+    /// ]).unwrap_err();
+    ///
+    /// assert_eq!(error.index(), Some(1));
+    /// assert_eq!(error.pattern(), Some("(bar"));
+    /// ```
+    pub fn try_new<I, S>(items: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (S, V)>,
+        S: Into<Pattern>,
+    {
+        RegexMapBuilder::new(items).build()
+    }
+
+    /// Get an iterator over all values whose regular expression matches the given key.
+    ///
+    /// To get first matching value, use can use `.next()` on the returned iterator:
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    ("foo", 1),
+    ///    ("bar", 2),
+    /// ]);
+    ///
+    /// assert_eq!(map.get("foo").next(), Some(&1));
+    /// ```
+    pub fn get(&self, key: &H) -> impl Iterator<Item = &V> {
+        self.compiled()
+            .set
+            .matches(key)
+            .into_indices()
+            .map(move |i| &self.values[i])
+    }
+
+    /// Get an iterator over mutable references to all values whose regular expression matches the given key.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([
+    ///    ("foo", 0),
+    ///    ("bar", 0),
+    /// ]);
+    ///
+    /// map.get_mut("foo").for_each(|count| *count += 1);
+    /// map.get_mut("foobar").for_each(|count| *count += 1);
+    ///
+    /// assert_eq!(map.values().cloned().collect::<Vec<_>>(), vec![2, 1]);
+    /// ```
+    pub fn get_mut(&mut self, key: &H) -> impl Iterator<Item = &mut V> {
+        self.compiled();
+        // Borrow the field rather than `self`, so that the values can be borrowed mutably alongside.
+        let matches = self.compiled.get().unwrap().set.matches(key);
+        self.values
+            .iter_mut()
+            .enumerate()
+            .filter(move |(i, _)| matches.matched(*i))
+            .map(|(_, value)| value)
+    }
+
+    /// Get the value of the highest-priority entry whose regular expression matches the given key.
+    ///
+    /// All entries have the same priority unless given one with `RegexMapBuilder::priority`,
+    /// `RegexMap::insert_with_priority` or `RegexMap::set_priority`, so by default this is the first
+    /// matching entry in insertion order. The sets of the map are then searched in order, stopping at the
+    /// first that matches. Otherwise the key is matched against all the sets once, as by `RegexMap::get`,
+    /// and the matching entry with the highest priority wins.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([
+    ///    ("^/api/", "api"),
+    ///    ("^/api/admin/", "admin"),
+    ///    ("", "fallback"),
+    /// ]);
+    ///
+    /// assert_eq!(map.get_first("/api/admin/users"), Some(&"api"));
+    /// assert_eq!(map.get_first("/index.html"), Some(&"fallback"));
+    ///
+    /// map.set_priority(1, 10);
+    /// map.set_priority(2, -10);
+    ///
+    /// assert_eq!(map.get_first("/api/admin/users"), Some(&"admin"));
+    /// assert_eq!(map.get_first("/api/users"), Some(&"api"));
+    ///
+    /// map.remove(2);
+    /// assert_eq!(map.get_first("/index.html"), None);
+    /// ```
+    pub fn get_first(&self, key: &H) -> Option<&V> {
+        let set = &self.compiled().set;
+        let first = match self.uniform {
+            true => set.first(key),
+            false => set
+                .matches(key)
+                .into_indices()
+                .max_by_key(|&i| (self.priorities[i], Reverse(i))),
+        };
+        first.map(|i| &self.values[i])
+    }
+
+    /// Set the priority of the entry at index `index`, used by `RegexMap::get_first`.
+    ///
+    /// Entries with a higher priority win; entries with the same priority are ordered by insertion. The
+    /// default priority of every entry is `0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_priority(&mut self, index: usize, priority: i32) {
+        self.priorities[index] = priority;
+        self.check_uniform();
+    }
+
+    /// Get the value of the most specific entry whose regular expression matches the given key.
+    ///
+    /// ```
+    /// use regex_map::{RegexMap, Specificity};
+    ///
+    /// let map = RegexMap::new([
+    ///    (r"\.com$", "com"),
+    ///    (r"example\.com$", "example"),
+    ///    (r"^api\.example\.com$", "api"),
+    ///    (r"^[a-z]+\.[a-z]+\.com$", "subdomain"),
+    /// ]);
+    ///
+    /// let key = "api.example.com";
+    /// assert_eq!(map.get_most_specific(key, Specificity::LongestMatch), Some(&"api"));
+    /// assert_eq!(map.get_most_specific(key, Specificity::MostLiterals), Some(&"api"));
+    /// assert_eq!(map.get_most_specific("www.example.com", Specificity::MostLiterals), Some(&"example"));
+    /// assert_eq!(map.get_most_specific("www.example.com", Specificity::Anchored), Some(&"subdomain"));
+    ///
+    /// let map = RegexMap::new([("a|abc", "alternation"), ("ab", "prefix")]);
+    /// assert_eq!(map.get_most_specific("abc", Specificity::LongestMatch), Some(&"alternation"));
+    /// ```
+    pub fn get_most_specific(&self, key: &H, specificity: Specificity) -> Option<&V> {
+        let matches = self.compiled().set.matches(key).into_indices();
+        let best = match specificity {
+            Specificity::LongestMatch => {
+                let len = |i| {
+                    H::find(self.regex(i), key).map_or(0, |m| self.longest_len(i, key, m.start))
+                };
+                specificity::max_by(matches, |a, b| len(a).cmp(&len(b)))
+            }
+            Specificity::MostLiterals => specificity::max_by(matches, |a, b| {
+                self.specifics(a).literals.cmp(&self.specifics(b).literals)
+            }),
+            Specificity::Anchored => specificity::max_by(matches, |a, b| {
+                self.specifics(a).anchors.cmp(&self.specifics(b).anchors)
+            }),
+        };
+        best.map(|i| &self.values[i])
+    }
+
+    /// Get the value of the most specific entry whose regular expression matches the given key, as
+    /// ranked by `compare`, which returns `Ordering::Greater` when its first argument is more specific.
+    /// Ties are broken in favour of the entry inserted first.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    ("^/", ("root", 0)),
+    ///    ("^/api/", ("api", 1)),
+    ///    ("^/api/v1/", ("v1", 1)),
+    /// ]);
+    ///
+    /// let best = map.get_most_specific_by("/api/v1/users", |a, b| {
+    ///     a.value.1.cmp(&b.value.1).then(b.pattern.len().cmp(&a.pattern.len()))
+    /// });
+    /// assert_eq!(best, Some(&("api", 1)));
+    /// ```
+    pub fn get_most_specific_by<F>(&self, key: &H, mut compare: F) -> Option<&V>
+    where
+        F: FnMut(&Entry<'_, V>, &Entry<'_, V>) -> Ordering,
+    {
+        let entry = |index: usize| Entry {
+            index,
+            pattern: &self.exprs[index],
+            value: &self.values[index],
+        };
+        let matches = self.compiled().set.matches(key).into_indices();
+        specificity::max_by(matches, |a, b| compare(&entry(a), &entry(b))).map(|i| &self.values[i])
+    }
+
+    /// Get an iterator over all values whose regular expression matches the given key, together with the
+    /// captures of that regular expression.
+    ///
+    /// The regular expression of an entry is compiled on its own the first time it matches, so this
+    /// costs the same as `RegexMap::get` plus one search per matching entry.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    ("([a-z]+)@example\\.com", 1),
+    ///    ("@(?<domain>[a-z.]+)", 2),
+    /// ]);
+    ///
+    /// let matches = map.get_with_match("john@example.com").collect::<Vec<_>>();
+    ///
+    /// assert_eq!(matches[0].0, &1);
+    /// assert_eq!(matches[0].1.get(0).unwrap().range(), 0..16);
+    /// assert_eq!(&matches[0].1[1], "john");
+    /// assert_eq!(matches[1].0, &2);
+    /// assert_eq!(matches[1].1.get(0).unwrap().range(), 4..16);
+    /// assert_eq!(&matches[1].1["domain"], "example.com");
+    /// ```
+    pub fn get_with_match<'a>(
+        &'a self,
+        key: &'a H,
+    ) -> impl Iterator<Item = (&'a V, H::Captures<'a>)> {
+        self.compiled()
+            .set
+            .matches(key)
+            .into_indices()
+            .map(move |i| {
+                let captures = H::captures(self.regex(i), key).expect(
+                    "the set and the individual regular expression should agree on a match",
+                );
+                (&self.values[i], captures)
+            })
+    }
+
+    /// Get an iterator over the successive non-overlapping matches of any regular expression in
+    /// `haystack`, as the span of each match with the value of its entry.
+    ///
+    /// `kind` picks the match when several start at the same position. All regular expressions are
+    /// searched for at once, with automata built on the first call.
+    ///
+    /// ```
+    /// use regex_map::{MatchKind, RegexMap};
+    ///
+    /// let map = RegexMap::new([
+    ///    ("[0-9]+", "number"),
+    ///    ("[0-9]+px", "length"),
+    ///    ("[a-z]+", "word"),
+    /// ]);
+    ///
+    /// let text = "width: 10px";
+    /// assert_eq!(
+    ///     map.find_iter(text, MatchKind::LeftmostFirst).collect::<Vec<_>>(),
+    ///     vec![(0..5, &"word"), (7..9, &"number"), (9..11, &"word")],
+    /// );
+    /// assert_eq!(
+    ///     map.find_iter(text, MatchKind::LeftmostLongest).collect::<Vec<_>>(),
+    ///     vec![(0..5, &"word"), (7..11, &"length")],
+    /// );
+    /// ```
+    pub fn find_iter<'a>(
+        &'a self,
+        haystack: &'a H,
+        kind: MatchKind,
+    ) -> impl Iterator<Item = (Range<usize>, &'a V)> + 'a {
+        self.search(haystack, kind)
+            .map(|m| (m.range(), &self.values[m.pattern().as_usize()]))
+    }
+
+    /// Replace every successive non-overlapping match of any regular expression in `haystack` with the
+    /// replacement of its entry, in a single pass.
+    ///
+    /// Matches are found as by `RegexMap::find_iter` with `MatchKind::LeftmostFirst`, so an entry
+    /// inserted earlier wins when several match at the same position. The captures a replacement is
+    /// expanded with are those of the regular expression of its entry alone, compiled the first time
+    /// it matches. When nothing matches, `haystack` is returned without copying it.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    (r"(?<user>[a-z]+)@[a-z]+\.com", "$user@<redacted>"),
+    ///    (r"\b[0-9]{1,3}(\.[0-9]{1,3}){3}\b", "<ip>"),
+    ///    (r"token=[0-9a-f]+", "token=<redacted>"),
+    /// ]);
+    ///
+    /// assert_eq!(
+    ///     map.replace_all("login john@example.com from 10.0.0.1 token=f00d"),
+    ///     "login john@<redacted> from <ip> token=<redacted>",
+    /// );
+    /// assert!(matches!(map.replace_all("logout"), std::borrow::Cow::Borrowed("logout")));
+    /// ```
+    pub fn replace_all<'h>(&self, haystack: &'h H) -> Cow<'h, H>
+    where
+        V: Replacement<H>,
+    {
+        let mut matches = self.search(haystack, MatchKind::LeftmostFirst).peekable();
+        if matches.peek().is_none() {
+            return Cow::Borrowed(haystack);
+        }
+        let mut replaced = H::Owned::default();
+        let mut last = 0;
+        for m in matches {
+            let i = m.pattern().as_usize();
+            let captures = H::captures_at(self.regex(i), haystack, m.start())
+                .expect("the set and the individual regular expression should agree on a match");
+            H::append(&mut replaced, haystack, last..m.start());
+            self.values[i].replace_append(&captures, &mut replaced);
+            last = m.end();
+        }
+        H::append(&mut replaced, haystack, last..H::as_bytes(haystack).len());
+        Cow::Owned(replaced)
+    }
+
+    /// Get an iterator over every match of every regular expression in `haystack`, including
+    /// overlapping ones, as the index of the entry, the span of the match and the value of the entry.
+    ///
+    /// Each regular expression is reported at most once per end position, with the leftmost start
+    /// matching there. Matches are reported in ascending order of their end. All regular
+    /// expressions are searched for at once, with lazy DFAs built on the first call and split across
+    /// shards like the sets of the map.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([
+    ///    ("[0-9]+", "number"),
+    ///    ("[0-9]+px", "length"),
+    ///    ("[a-z]+", "word"),
+    /// ]);
+    ///
+    /// assert_eq!(
+    ///     map.find_overlapping_iter("10px").collect::<Vec<_>>(),
+    ///     vec![
+    ///         (0, 0..1, &"number"),
+    ///         (0, 0..2, &"number"),
+    ///         (2, 2..3, &"word"),
+    ///         (1, 0..4, &"length"),
+    ///         (2, 2..4, &"word"),
+    ///     ],
+    /// );
+    /// ```
+    ///
+    /// The lazy DFAs do not support Unicode word boundaries (`\b` with Unicode enabled) on non-ASCII
+    /// text, so each match of an entry that has any is confirmed by its regular expression alone:
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([(r"\bfoo\b", "foo"), (r"\w+", "word")]);
+    ///
+    /// let matches = map.find_overlapping_iter("café foo").collect::<Vec<_>>();
+    /// assert!(matches.contains(&(1, 0..5, &"word")));
+    /// assert!(matches.contains(&(0, 6..9, &"foo")));
+    /// assert_eq!(matches.iter().filter(|(index, ..)| *index == 0).count(), 1);
+    /// ```
+    pub fn find_overlapping_iter<'a>(&'a self, haystack: &'a H) -> Overlapping<'a, V> {
+        Overlapping::new(self.overlapping(), &self.values, H::as_bytes(haystack))
+    }
+
+    /// Check if the given key matches any of the regular expressions.
+    pub fn contains_key(&self, key: &H) -> bool {
+        self.compiled().set.is_match(key)
+    }
+
+    /// Get an iterator over all (expression, value) pairs, in insertion order.
+    ///
+    /// The expressions are the ones handed to the underlying set, i.e. with the flags of a `Pattern`
+    /// folded in.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let map = RegexMap::new([("foo", 1), ("bar", 2)]);
+    ///
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec![("foo", &1), ("bar", &2)]);
+    /// assert_eq!(map.patterns().collect::<Vec<_>>(), vec!["foo", "bar"]);
+    /// assert_eq!(map.values().collect::<Vec<_>>(), vec![&1, &2]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            inner: self.exprs.iter().zip(self.values.iter()),
+        }
+    }
+
+    /// Get an iterator over all regular expressions, in insertion order.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.exprs.iter().map(String::as_str)
+    }
+
+    /// Get an iterator over all values, in insertion order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.values.iter()
+    }
+
+    /// Get an iterator over mutable references to all values, in insertion order.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([("foo", 1), ("bar", 2)]);
+    /// map.values_mut().for_each(|value| *value *= 10);
+    ///
+    /// assert_eq!(map.get("foo").cloned().collect::<Vec<_>>(), vec![10]);
+    /// ```
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.values.iter_mut()
+    }
+
+    /// Number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Append a new entry to the map, returning its index.
+    ///
+    /// The regular expression is checked on its own right away, but the underlying set is only rebuilt
+    /// on the next lookup or call to `RegexMap::compile`.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([("foo", 1)]);
+    /// map.insert("bar", 2).unwrap();
+    /// map.insert("baz", 3).unwrap();
+    ///
+    /// assert!(map.is_stale());
+    /// assert!(map.insert("(qux", 4).is_err());
+    /// assert_eq!(map.get("foo bar baz").cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+    /// assert!(!map.is_stale());
+    /// ```
+    pub fn insert<S: Into<Pattern>>(&mut self, expr: S, value: V) -> Result<usize, Error> {
+        self.insert_with_priority(expr, value, 0)
+    }
+
+    /// Append a new entry to the map with the given priority, used by `RegexMap::get_first`, returning
+    /// its index.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([("^/api/", "api")]);
+    /// map.insert_with_priority("^/api/admin/", "admin", 1).unwrap();
+    ///
+    /// assert_eq!(map.get_first("/api/admin/users"), Some(&"admin"));
+    /// assert_eq!(map.get_first("/api/users"), Some(&"api"));
+    /// ```
+    pub fn insert_with_priority<S: Into<Pattern>>(
+        &mut self,
+        expr: S,
+        value: V,
+        priority: i32,
+    ) -> Result<usize, Error> {
+        let expr = expr.into().to_regex(self.options.anchor);
+        let index = self.exprs.len();
+        if let Err(error) = H::build_regex(&expr, &self.options) {
+            return Err(Error::Pattern {
+                index,
+                pattern: expr,
+                error,
+            });
+        }
+
+        self.exprs.push(expr);
+        self.values.push(value);
+        self.uniform &= self
+            .priorities
+            .first()
+            .is_none_or(|&first| first == priority);
+        self.priorities.push(priority);
+        self.compiled = OnceLock::new();
+        Ok(index)
+    }
+
+    /// Remove the entry at index `index`, returning its value. The indices of later entries shift down
+    /// by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> V {
+        self.exprs.remove(index);
+        self.priorities.remove(index);
+        self.check_uniform();
+        self.compiled = OnceLock::new();
+        self.values.remove(index)
+    }
+
+    /// Remove the first entry whose regular expression is `expr`, returning its value.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([("foo", 1), ("bar", 2)]);
+    ///
+    /// assert_eq!(map.remove_pattern("foo"), Some(1));
+    /// assert_eq!(map.remove_pattern("foo"), None);
+    /// assert_eq!(map.get("foo bar").cloned().collect::<Vec<_>>(), vec![2]);
+    /// ```
+    pub fn remove_pattern<S: Into<Pattern>>(&mut self, expr: S) -> Option<V> {
+        let expr = expr.into().to_regex(self.options.anchor);
+        let index = self.exprs.iter().position(|e| *e == expr)?;
+        Some(self.remove(index))
+    }
+
+    /// Keep only the entries for which `f` returns `true`, preserving their order.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let mut map = RegexMap::new([("foo", 1), ("bar", 2), ("baz", 3)]);
+    /// map.retain(|_, value| *value != 2);
+    ///
+    /// assert_eq!(map.get("foo bar baz").cloned().collect::<Vec<_>>(), vec![1, 3]);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str, &mut V) -> bool,
+    {
+        let keep = self
+            .exprs
+            .iter()
+            .zip(self.values.iter_mut())
+            .map(|(expr, value)| f(expr, value))
+            .collect::<Vec<_>>();
+        if keep.iter().all(|&keep| keep) {
+            return;
+        }
+
+        retain_by(&mut self.exprs, &keep);
+        retain_by(&mut self.values, &keep);
+        retain_by(&mut self.priorities, &keep);
+        self.check_uniform();
+        self.compiled = OnceLock::new();
+    }
+
+    /// Rebuild the underlying set if the map was edited since it was last built.
+    ///
+    /// Lookups do this on their own, but panic if the set cannot be built (e.g. because the edits made it
+    /// exceed the size limit); calling this first reports such errors instead.
+    pub fn compile(&mut self) -> Result<(), Error> {
+        if self.compiled.get().is_none() {
+            self.compiled = OnceLock::from(self.build()?);
+        }
+        Ok(())
+    }
+
+    /// Check if the map was edited since the underlying set was last built.
+    pub fn is_stale(&self) -> bool {
+        self.compiled.get().is_none()
+    }
+
+    /// Statistics about how the map was compiled, rebuilding the underlying sets first if it is stale.
+    ///
+    /// Entries whose regular expression is a literal anchored at both ends, such as `^/healthz$`, are
+    /// looked up in a hash table rather than searched for. The others are split across several sets
+    /// when a single one would exceed the size limit, or when `RegexMapBuilder::shard_len` caps the
+    /// number of entries per set.
+    ///
+    /// ```
+    /// use regex_map::RegexMapBuilder;
+    ///
+    /// let map = RegexMapBuilder::new((0..10).map(|i| (format!("^{}+$", i), i)))
+    ///     .shard_len(4)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(map.stats().shards, vec![4, 4, 2]);
+    /// assert_eq!(map.get("99").cloned().collect::<Vec<_>>(), vec![9]);
+    ///
+    /// let map = RegexMapBuilder::new([
+    ///    ("^/healthz$", 1),
+    ///    ("^/api/", 2),
+    ///    (r"\A/api/users\z", 3),
+    /// ])
+    /// .build()
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.stats().literals, 2);
+    /// assert_eq!(map.stats().shards, vec![1]);
+    /// assert_eq!(map.get("/api/users").cloned().collect::<Vec<_>>(), vec![2, 3]);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let set = &self.compiled().set;
+        Stats {
+            literals: set.literal_len(),
+            prefiltered: set.prefilter_len(),
+            shards: set
+                .shards()
+                .ranges()
+                .iter()
+                .map(|range| range.len())
+                .collect(),
+        }
+    }
+
+    /// The successive non-overlapping matches of any regular expression in `haystack`.
+    fn search<'a>(&'a self, haystack: &'a H, kind: MatchKind) -> impl Iterator<Item = Match> + 'a {
+        let finder = self
+            .compiled()
+            .finder
+            .get_or_init(|| Finder::new(&self.exprs, &self.options, H::UTF8));
+        let mut searcher = Searcher::new(Input::new(H::as_bytes(haystack)));
+        std::iter::from_fn(move || searcher.advance(|input| Ok(finder.find(input, kind))))
+    }
+
+    /// The underlying set, rebuilt first if the map is stale.
+    fn compiled(&self) -> &Compiled<H> {
+        self.compiled.get_or_init(|| match self.build() {
+            Ok(compiled) => compiled,
+            Err(error) => panic!("{}", error),
+        })
+    }
+
+    fn build(&self) -> Result<Compiled<H>, Error> {
+        Ok(Compiled {
+            set: Matcher::build(&self.exprs, &self.options)?,
+            finder: OnceLock::new(),
+            overlapping: OnceLock::new(),
+            specifics: self.exprs.iter().map(|_| OnceLock::new()).collect(),
+            longest: self.exprs.iter().map(|_| OnceLock::new()).collect(),
+        })
+    }
+
+    /// A map whose underlying set is already built, for precompiled maps.
+    #[cfg(feature = "dfa")]
+    pub(crate) fn from_parts(
+        exprs: Vec<String>,
+        values: Vec<V>,
+        priorities: Vec<i32>,
+        options: Options,
+        set: Matcher<H>,
+    ) -> Self {
+        let compiled = Compiled {
+            set,
+            finder: OnceLock::new(),
+            overlapping: OnceLock::new(),
+            specifics: exprs.iter().map(|_| OnceLock::new()).collect(),
+            longest: exprs.iter().map(|_| OnceLock::new()).collect(),
+        };
+        let mut map = RegexMap {
+            exprs,
+            values,
+            priorities,
+            uniform: true,
+            options,
+            compiled: OnceLock::from(compiled),
+        };
+        map.check_uniform();
+        map
+    }
+
+    #[cfg(feature = "dfa")]
+    pub(crate) fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub(crate) fn value(&self, index: usize) -> &V {
+        &self.values[index]
+    }
+
+    pub(crate) fn priorities(&self) -> &[i32] {
+        &self.priorities
+    }
+
+    /// The automata for overlapping searches, built first if needed.
+    pub(crate) fn overlapping(&self) -> &OverlappingFinder {
+        self.compiled()
+            .overlapping
+            .get_or_init(|| OverlappingFinder::new(&self.exprs, &self.options, H::UTF8))
+    }
+
+    fn check_uniform(&mut self) {
+        let priorities = &self.priorities;
+        self.uniform = priorities.iter().all(|&priority| priority == priorities[0]);
+    }
+
+    /// The regular expression of the entry at index `i`, compiled on first use.
+    fn regex(&self, i: usize) -> &H::Regex {
+        self.compiled().set.regex(i)
+    }
+
+    /// The length of the longest match of the entry at index `i` in `key` starting at `start`.
+    fn longest_len(&self, i: usize, key: &H, start: usize) -> usize {
+        let regex = self.compiled().longest[i]
+            .get_or_init(|| find::longest(&self.exprs[i], &self.options, H::UTF8));
+        let input = Input::new(H::as_bytes(key))
+            .range(start..)
+            .anchored(Anchored::Yes);
+        regex
+            .search_half(&input)
+            .map_or(0, |end| end.offset() - start)
+    }
+
+    /// The properties of the entry at index `i` used by `RegexMap::get_most_specific`, computed on
+    /// first use.
+    fn specifics(&self, i: usize) -> Specifics {
+        *self.compiled().specifics[i]
+            .get_or_init(|| Specifics::new(&self.exprs[i], &self.options, H::UTF8))
+    }
+}
+
+/// Builder for a `RegexMap` with non-default options, forwarded to `regex::RegexSetBuilder`.
+///
+/// ```
+/// use regex_map::RegexMapBuilder;
+///
+/// let map = RegexMapBuilder::new([
+///    ("^foo$", 1),
+///    ("^bar", 2),
+/// ])
+/// .case_insensitive(true)
+/// .multi_line(true)
+/// .build()
+/// .unwrap();
+///
+/// assert_eq!(map.get("FOO").cloned().collect::<Vec<_>>(), vec![1]);
+/// assert_eq!(map.get("foo\nBar").cloned().collect::<Vec<_>>(), vec![1, 2]);
+/// ```
+pub struct RegexMapBuilder<V, H: ?Sized + Haystack> {
+    patterns: Vec<Pattern>,
+    values: Vec<V>,
+    priorities: Vec<i32>,
+    options: Options,
+    haystack: PhantomData<H>,
+}
+
+impl<V, H: ?Sized + Haystack> RegexMapBuilder<V, H> {
+    /// Create a new builder from iterator over (expression, value) pairs, where the expression is `&str`-like
+    /// or a `Pattern`.
+    pub fn new<I, S>(items: I) -> Self
+    where
+        I: IntoIterator<Item = (S, V)>,
+        S: Into<Pattern>,
+    {
+        let mut patterns = Vec::new();
+        let mut values = Vec::new();
+        for (expr, value) in items {
+            patterns.push(expr.into());
+            values.push(value);
+        }
+
+        RegexMapBuilder {
+            priorities: vec![0; values.len()],
+            patterns,
+            values,
+            options: Options::default(),
+            haystack: PhantomData,
+        }
+    }
+
+    /// Build the `RegexMap`, returning an error if any of the regular expressions is invalid or the set
+    /// exceeds the configured size limits.
+    pub fn build(self) -> Result<RegexMap<V, H>, Error> {
+        let exprs = self
+            .patterns
+            .iter()
+            .map(|pattern| pattern.to_regex(self.options.anchor))
+            .collect::<Vec<_>>();
+        let mut map = RegexMap {
+            exprs,
+            values: self.values,
+            priorities: self.priorities,
+            uniform: true,
+            options: self.options,
+            compiled: OnceLock::new(),
+        };
+        map.check_uniform();
+        map.compile()?;
+        Ok(map)
+    }
+
+    /// Set the priority of the entry at index `index`, used by `RegexMap::get_first`, like
+    /// `RegexMap::set_priority`. Defaults to `0` for every entry.
+    ///
+    /// ```
+    /// use regex_map::RegexMapBuilder;
+    ///
+    /// let map = RegexMapBuilder::new([
+    ///    ("^/api/", "api"),
+    ///    ("^/api/admin/", "admin"),
+    /// ])
+    /// .priority(1, 1)
+    /// .build()
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.get_first("/api/admin/users"), Some(&"admin"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn priority(mut self, index: usize, priority: i32) -> Self {
+        self.priorities[index] = priority;
+        self
+    }
+
+    /// Set where every regular expression must match within a key. Defaults to `Anchor::Unanchored`.
+    ///
+    /// ```
+    /// use regex_map::{Anchor, RegexMapBuilder};
+    ///
+    /// let map = RegexMapBuilder::new([
+    ///    ("foo|bar", 1),
+    ///    ("[a-z]+", 2),
+    /// ])
+    /// .anchor(Anchor::Full)
+    /// .build()
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.get("foo").cloned().collect::<Vec<_>>(), vec![1, 2]);
+    /// assert_eq!(map.get("foobar").cloned().collect::<Vec<_>>(), vec![2]);
+    /// assert_eq!(map.get("XXX foo XXX").cloned().collect::<Vec<_>>(), Vec::<i32>::new());
+    ///
+    /// let map = RegexMapBuilder::new([
+    ///    ("foo|bar", 1),
+    /// ])
+    /// .anchor(Anchor::Prefix)
+    /// .build()
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.get("barbaz").cloned().collect::<Vec<_>>(), vec![1]);
+    /// assert_eq!(map.get("bazbar").cloned().collect::<Vec<_>>(), Vec::<i32>::new());
+    /// ```
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.options.anchor = anchor;
+        self
+    }
+
+    /// Set the value for the case insensitive (`i`) flag of every regular expression.
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.options.case_insensitive = yes;
+        self
+    }
+
+    /// Set the value for the multi-line matching (`m`) flag of every regular expression.
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.options.multi_line = yes;
+        self
+    }
+
+    /// Set the value for the any character (`s`) flag of every regular expression, making `.` match `\n`.
+    pub fn dot_matches_new_line(mut self, yes: bool) -> Self {
+        self.options.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Set the value for the Unicode (`u`) flag of every regular expression. Enabled by default.
+    pub fn unicode(mut self, yes: bool) -> Self {
+        self.options.unicode = yes;
+        self
+    }
+
+    /// Set the approximate size limit, in bytes, of each compiled set. Entries that do not fit in a
+    /// single set are split across several, see `RegexMap::stats`.
+    ///
+    /// ```
+    /// use regex_map::{MatchKind, RegexMapBuilder};
+    ///
+    /// let entries = (0..200).map(|i| (format!("[a-z]{{1,20}}-{}-[0-9]{{1,30}}", i), i));
+    /// let map = RegexMapBuilder::new(entries).size_limit(200_000).build().unwrap();
+    ///
+    /// assert!(map.stats().shards.len() > 1);
+    /// assert_eq!(map.get_first("abc-199-42"), Some(&199));
+    /// assert_eq!(
+    ///     map.find_iter("abc-7-1 x-199-2", MatchKind::LeftmostFirst).collect::<Vec<_>>(),
+    ///     vec![(0..7, &7), (8..15, &199)],
+    /// );
+    /// ```
+    pub fn size_limit(mut self, bytes: usize) -> Self {
+        self.options.size_limit = Some(bytes);
+        self
+    }
+
+    /// Set the approximate size of the cache, in bytes, used by the lazy DFA of the set.
+    ///
+    /// With the `dfa` feature, this also bounds the size of the DFA built by `RegexMap::to_bytes`, and the
+    /// memory used to build it.
+    pub fn dfa_size_limit(mut self, bytes: usize) -> Self {
+        self.options.dfa_size_limit = Some(bytes);
+        self
+    }
+
+    /// Set whether to search for the literals required by each regular expression first, and only run
+    /// the regular expressions whose literals occur in the key.
+    ///
+    /// This pays off for large maps of regular expressions such as `error: .* timed out`, which cannot
+    /// match a key without containing `error: ` or ` timed out`. Regular expressions without such
+    /// literals are searched for as usual. By default, the prefilter is used when most regular
+    /// expressions of a large map have required literals.
+    ///
+    /// ```
+    /// use regex_map::RegexMapBuilder;
+    ///
+    /// let map = RegexMapBuilder::new([
+    ///    ("error: .* timed out", 1),
+    ///    ("(GET|POST) /api/", 2),
+    ///    ("[0-9]+", 3),
+    /// ])
+    /// .prefilter(true)
+    /// .build()
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.stats().prefiltered, 2);
+    /// assert_eq!(map.get("error: GET /api/users timed out").cloned().collect::<Vec<_>>(), vec![1, 2]);
+    /// assert_eq!(map.get("error: 404").cloned().collect::<Vec<_>>(), vec![3]);
+    /// ```
+    pub fn prefilter(mut self, yes: bool) -> Self {
+        self.options.prefilter = Some(yes);
+        self
+    }
+
+    /// Set the maximum number of entries per underlying set, so that a large map is built as several
+    /// smaller sets. By default, entries are only split when a set would exceed the size limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero.
+    pub fn shard_len(mut self, len: usize) -> Self {
+        assert!(len > 0, "a shard must hold at least one entry");
+        self.options.shard_len = Some(len);
+        self
+    }
+}
+
+/// Keep the items whose corresponding flag in `keep` is set.
+fn retain_by<T>(items: &mut Vec<T>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    items.retain(|_| *keep.next().unwrap());
+}
+
+impl<H: ?Sized + Haystack> RegexMap<(), H> {
+    /// Check every regular expression on its own, without building the underlying set, and report all
+    /// the invalid ones at once. Spans point into the source of each pattern, before its flags are applied.
+    ///
+    /// The value type plays no role in validation, which is why this is only defined on `RegexMap<()>`;
+    /// call it as `RegexMap::validate(...)`.
+    ///
+    /// ```
+    /// use regex_map::RegexMap;
+    ///
+    /// let report = RegexMap::validate(["foo", "(bar", "baz", "[qux"]).unwrap_err();
+    ///
+    /// assert_eq!(report.errors.len(), 2);
+    /// assert_eq!(report.errors[0].index, 1);
+    /// assert_eq!(report.errors[0].pattern, "(bar");
+    /// assert_eq!(report.errors[1].index, 3);
+    /// assert_eq!(report.errors[1].span, 0..1);
+    /// ```
+    ///
+    /// Only the syntax is checked; a set that exceeds the compiled size limit is still reported by
+    /// `RegexMap::try_new`.
+    pub fn validate<I, S>(exprs: I) -> Result<(), ValidationReport>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Pattern>,
+    {
+        error::validate(exprs, H::UTF8)
+    }
+}
+
+impl<V, H, S> TryFrom<Vec<(S, V)>> for RegexMap<V, H>
+where
+    H: ?Sized + Haystack,
+    S: Into<Pattern>,
+{
+    type Error = Error;
+
+    fn try_from(items: Vec<(S, V)>) -> Result<Self, Error> {
+        Self::try_new(items)
+    }
+}
+
+impl<V, H, S, const N: usize> TryFrom<[(S, V); N]> for RegexMap<V, H>
+where
+    H: ?Sized + Haystack,
+    S: Into<Pattern>,
+{
+    type Error = Error;
+
+    fn try_from(items: [(S, V); N]) -> Result<Self, Error> {
+        Self::try_new(items)
+    }
+}
+
+impl<V: Clone, H: ?Sized + Haystack> Clone for RegexMap<V, H> {
+    fn clone(&self) -> Self {
+        RegexMap {
+            exprs: self.exprs.clone(),
+            values: self.values.clone(),
+            priorities: self.priorities.clone(),
+            uniform: self.uniform,
+            options: self.options.clone(),
+            compiled: self.compiled.clone(),
+        }
+    }
+}
+
+impl<H: ?Sized + Haystack> Clone for Compiled<H> {
+    fn clone(&self) -> Self {
+        Compiled {
+            set: self.set.clone(),
+            finder: self.finder.clone(),
+            overlapping: self.overlapping.clone(),
+            specifics: self.specifics.clone(),
+            longest: self.longest.clone(),
+        }
+    }
+}
+
+impl<V, H: ?Sized + Haystack> Default for RegexMap<V, H> {
+    fn default() -> Self {
+        RegexMap {
+            exprs: Vec::new(),
+            values: Vec::new(),
+            priorities: Vec::new(),
+            uniform: true,
+            options: Options::default(),
+            compiled: OnceLock::from(Compiled {
+                set: Matcher::empty(),
+                finder: OnceLock::new(),
+                overlapping: OnceLock::new(),
+                specifics: Vec::new(),
+                longest: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl<V: fmt::Debug, H: ?Sized + Haystack> fmt::Debug for RegexMap<V, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Collect (expression, value) pairs into a `RegexMap`.
+///
+/// ```
+/// use regex_map::RegexMap;
+///
+/// let map: RegexMap<_> = ["foo", "bar"].into_iter().zip(1..).collect();
+///
+/// assert_eq!(format!("{:?}", map), r#"{"foo": 1, "bar": 2}"#);
+/// ```
+///
+/// # Panics
+///
+/// Panics if any of the regular expressions is invalid, like `RegexMap::new`.
+impl<V, H, S> FromIterator<(S, V)> for RegexMap<V, H>
+where
+    H: ?Sized + Haystack,
+    S: Into<Pattern>,
+{
+    fn from_iter<I: IntoIterator<Item = (S, V)>>(items: I) -> Self {
+        RegexMap::new(items)
+    }
+}
+
+/// Append (expression, value) pairs to a `RegexMap`, as with `RegexMap::insert`, rebuilding the
+/// underlying set only once.
+///
+/// # Panics
+///
+/// Panics if any of the regular expressions is invalid.
+impl<V, H, S> Extend<(S, V)> for RegexMap<V, H>
+where
+    H: ?Sized + Haystack,
+    S: Into<Pattern>,
+{
+    fn extend<I: IntoIterator<Item = (S, V)>>(&mut self, items: I) {
+        for (expr, value) in items {
+            if let Err(error) = self.insert(expr, value) {
+                panic!("{}", error);
+            }
+        }
+    }
+}
+
+/// Iterator over the (expression, value) pairs of a `RegexMap`, created by `RegexMap::iter`.
+pub struct Iter<'a, V> {
+    inner: std::iter::Zip<std::slice::Iter<'a, String>, std::slice::Iter<'a, V>>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (&'a str, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(expr, value)| (expr.as_str(), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for Iter<'_, V> {}
+
+/// Owning iterator over the (expression, value) pairs of a `RegexMap`.
+pub struct IntoIter<V> {
+    inner: std::iter::Zip<std::vec::IntoIter<String>, std::vec::IntoIter<V>>,
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = (String, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for IntoIter<V> {}
+
+impl<'a, V, H: ?Sized + Haystack> IntoIterator for &'a RegexMap<V, H> {
+    type Item = (&'a str, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Iter<'a, V> {
+        self.iter()
+    }
+}
+
+impl<V, H: ?Sized + Haystack> IntoIterator for RegexMap<V, H> {
+    type Item = (String, V);
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(self) -> IntoIter<V> {
+        IntoIter {
+            inner: self.exprs.into_iter().zip(self.values),
+        }
+    }
+}
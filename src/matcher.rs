@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::sync::OnceLock;
+
+use aho_corasick::AhoCorasick;
+use regex_syntax::hir::literal::{ExtractKind, Extractor, Seq};
+use regex_syntax::hir::{Hir, HirKind, Look};
+
+#[cfg(feature = "dfa")]
+use crate::dfa::Dfa;
+use crate::error::Error;
+use crate::haystack::Haystack;
+use crate::options::Options;
+use crate::shard::{self, Shards};
+
+/// Finds the entries of a `RegexMap` matching a key.
+///
+/// Entries whose regular expression only matches one exact string, such as `^/healthz$`, are looked up
+/// in a hash table by that string. With the prefilter, entries whose every match contains one of a few
+/// literals are only searched for when the key contains one of those literals. The others are searched
+/// with the sets of `Shards`.
+pub(crate) struct Matcher<H: ?Sized + Haystack> {
+    /// The regular expressions of all entries, with the options to compile them on their own.
+    exprs: Vec<String>,
+    options: Options,
+    /// The regular expression of each entry, compiled on first use.
+    regexes: Vec<OnceLock<H::Regex>>,
+    /// Indices of the literal entries, by the string they match.
+    literals: HashMap<Vec<u8>, Vec<usize>>,
+    prefilter: Option<Prefilter>,
+    /// Index of each entry handed to `shards`, in ascending order.
+    indices: Vec<usize>,
+    shards: Shards<H>,
+    /// The DFA of a precompiled map, which then finds every entry on its own.
+    #[cfg(feature = "dfa")]
+    dfa: Option<Dfa>,
+}
+
+/// Finds the entries that may match a key from the literals the key contains.
+#[derive(Clone)]
+struct Prefilter {
+    searcher: AhoCorasick,
+    /// Indices of the entries requiring each literal of `searcher`.
+    entries: Vec<Vec<usize>>,
+    /// The number of entries behind the prefilter.
+    len: usize,
+}
+
+/// The minimum number of entries with required literals for the prefilter to be used by default.
+const PREFILTER_MIN_LEN: usize = 64;
+
+/// Required literals shorter than this occur in too many keys to be worth prefiltering on.
+const PREFILTER_MIN_LITERAL_LEN: usize = 3;
+
+impl<H: ?Sized + Haystack> Matcher<H> {
+    pub(crate) fn build(exprs: &[String], options: &Options) -> Result<Self, Error> {
+        let mut literals = HashMap::<_, Vec<_>>::new();
+        let mut required = Vec::new();
+        let mut rest = Vec::new();
+        for (index, expr) in exprs.iter().enumerate() {
+            let hir = match options.parser(H::UTF8).parse(expr) {
+                Ok(hir) => hir,
+                Err(_) => {
+                    rest.push(index);
+                    continue;
+                }
+            };
+            if let Some(literal) = exact_literal(&hir) {
+                literals.entry(literal).or_default().push(index);
+            } else if let Some(seq) = required_literals(&hir) {
+                required.push((index, seq));
+            } else {
+                rest.push(index);
+            }
+        }
+
+        let use_prefilter = match options.prefilter {
+            Some(yes) => yes && !required.is_empty(),
+            None => required.len() >= PREFILTER_MIN_LEN && required.len() >= rest.len(),
+        };
+        let prefilter = match use_prefilter {
+            true => Some(Prefilter::new(&required)),
+            false => {
+                rest.extend(required.iter().map(|(index, _)| *index));
+                rest.sort_unstable();
+                None
+            }
+        };
+        let indices = rest;
+
+        let regexes = indices
+            .iter()
+            .map(|&i| exprs[i].clone())
+            .collect::<Vec<_>>();
+        let shards = Shards::build(&regexes, options).map_err(|mut error| {
+            if let Error::Pattern { index, .. } = &mut error {
+                *index = indices[*index];
+            }
+            error
+        })?;
+        let matcher = Matcher {
+            exprs: exprs.to_vec(),
+            options: options.clone(),
+            regexes: exprs.iter().map(|_| OnceLock::new()).collect(),
+            literals,
+            prefilter,
+            indices,
+            shards,
+            #[cfg(feature = "dfa")]
+            dfa: None,
+        };
+        // The entries behind the prefilter are not part of any set, so compile them on their own now to
+        // report errors at build time like the others.
+        if let Some(prefilter) = &matcher.prefilter {
+            for &index in prefilter.entries.iter().flatten() {
+                matcher.try_regex(index)?;
+            }
+        }
+        Ok(matcher)
+    }
+
+    pub(crate) fn empty() -> Self {
+        Matcher {
+            exprs: Vec::new(),
+            options: Options::default(),
+            regexes: Vec::new(),
+            literals: HashMap::new(),
+            prefilter: None,
+            indices: Vec::new(),
+            shards: Shards::empty(),
+            #[cfg(feature = "dfa")]
+            dfa: None,
+        }
+    }
+
+    #[cfg(feature = "dfa")]
+    pub(crate) fn from_dfa(exprs: &[String], options: &Options, dfa: Dfa) -> Self {
+        Matcher {
+            exprs: exprs.to_vec(),
+            options: options.clone(),
+            regexes: exprs.iter().map(|_| OnceLock::new()).collect(),
+            dfa: Some(dfa),
+            ..Matcher::empty()
+        }
+    }
+
+    pub(crate) fn matches(&self, key: &H) -> Matches<'_, H> {
+        #[cfg(feature = "dfa")]
+        if let Some(dfa) = &self.dfa {
+            return Matches {
+                literals: &[],
+                candidates: dfa.which(H::as_bytes(key)),
+                indices: &[],
+                shards: self.shards.matches(key),
+            };
+        }
+        Matches {
+            literals: self.literals(key),
+            candidates: self.candidates(key, |i| H::regex_is_match(self.regex(i), key)),
+            indices: &self.indices,
+            shards: self.shards.matches(key),
+        }
+    }
+
+    pub(crate) fn is_match(&self, key: &H) -> bool {
+        #[cfg(feature = "dfa")]
+        if let Some(dfa) = &self.dfa {
+            return !dfa.which(H::as_bytes(key)).is_empty();
+        }
+        !self.literals(key).is_empty()
+            || self.shards.is_match(key)
+            || !self
+                .candidates(key, |i| H::regex_is_match(self.regex(i), key))
+                .is_empty()
+    }
+
+    /// The lowest index of an entry matching `key`.
+    ///
+    /// The hash table and the prefilter are checked first, then the sets are searched in order until
+    /// the first one that matches, skipping those that only hold entries after a match already found.
+    pub(crate) fn first(&self, key: &H) -> Option<usize> {
+        #[cfg(feature = "dfa")]
+        if let Some(dfa) = &self.dfa {
+            return dfa.which(H::as_bytes(key)).into_iter().min();
+        }
+        let mut first = self.literals(key).first().copied();
+        if let Some(candidate) = self
+            .candidate_indices(key)
+            .into_iter()
+            .take_while(|&i| first.is_none_or(|first| i < first))
+            .find(|&i| H::regex_is_match(self.regex(i), key))
+        {
+            first = Some(candidate);
+        }
+        let end = first.map_or(self.indices.len(), |first| {
+            self.indices.partition_point(|&i| i < first)
+        });
+        match self.shards.first(key, end) {
+            Some(position) => Some(self.indices[position]),
+            None => first,
+        }
+    }
+
+    /// The regular expression of the entry at index `i`, compiled on first use.
+    pub(crate) fn regex(&self, i: usize) -> &H::Regex {
+        self.try_regex(i)
+            .expect("a regular expression accepted by the set should compile on its own")
+    }
+
+    fn try_regex(&self, i: usize) -> Result<&H::Regex, Error> {
+        if let Some(regex) = self.regexes[i].get() {
+            return Ok(regex);
+        }
+        let regex =
+            H::build_regex(&self.exprs[i], &self.options).map_err(|error| Error::Pattern {
+                index: i,
+                pattern: self.exprs[i].clone(),
+                error,
+            })?;
+        Ok(self.regexes[i].get_or_init(|| regex))
+    }
+
+    /// The number of entries behind the prefilter.
+    pub(crate) fn prefilter_len(&self) -> usize {
+        self.prefilter.as_ref().map_or(0, |prefilter| prefilter.len)
+    }
+
+    /// The number of entries served from the hash table.
+    pub(crate) fn literal_len(&self) -> usize {
+        self.literals.values().map(Vec::len).sum()
+    }
+
+    pub(crate) fn shards(&self) -> &Shards<H> {
+        &self.shards
+    }
+
+    fn literals(&self, key: &H) -> &[usize] {
+        self.literals
+            .get(H::as_bytes(key))
+            .map_or(&[], |indices| indices)
+    }
+
+    /// The entries behind the prefilter whose literals occur in `key` and that pass `is_match`, in
+    /// ascending order.
+    fn candidates<F>(&self, key: &H, mut is_match: F) -> Vec<usize>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut candidates = self.candidate_indices(key);
+        candidates.retain(|&i| is_match(i));
+        candidates
+    }
+
+    /// The entries behind the prefilter whose literals occur in `key`, in ascending order.
+    fn candidate_indices(&self, key: &H) -> Vec<usize> {
+        let prefilter = match &self.prefilter {
+            Some(prefilter) => prefilter,
+            None => return Vec::new(),
+        };
+        let mut candidates = prefilter
+            .searcher
+            .find_overlapping_iter(H::as_bytes(key))
+            .flat_map(|m| &prefilter.entries[m.pattern().as_usize()])
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Prefilter {
+    fn new(required: &[(usize, Seq)]) -> Self {
+        let mut ids = HashMap::new();
+        let mut literals = Vec::new();
+        let mut entries = Vec::<Vec<usize>>::new();
+        for (index, seq) in required {
+            for literal in seq.literals().into_iter().flatten() {
+                let id = *ids.entry(literal.as_bytes()).or_insert_with(|| {
+                    literals.push(literal.as_bytes());
+                    entries.push(Vec::new());
+                    literals.len() - 1
+                });
+                if entries[id].last() != Some(index) {
+                    entries[id].push(*index);
+                }
+            }
+        }
+        Prefilter {
+            searcher: AhoCorasick::new(literals)
+                .expect("the literals of the prefilter should not exceed the automaton limits"),
+            entries,
+            len: required.len(),
+        }
+    }
+}
+
+impl<H: ?Sized + Haystack> Clone for Matcher<H> {
+    fn clone(&self) -> Self {
+        Matcher {
+            exprs: self.exprs.clone(),
+            options: self.options.clone(),
+            regexes: self.regexes.clone(),
+            literals: self.literals.clone(),
+            prefilter: self.prefilter.clone(),
+            indices: self.indices.clone(),
+            shards: self.shards.clone(),
+            #[cfg(feature = "dfa")]
+            dfa: self.dfa.clone(),
+        }
+    }
+}
+
+/// The entries matched by `Matcher`, by index.
+pub(crate) struct Matches<'a, H: ?Sized + Haystack> {
+    /// The matching literal entries, in ascending order.
+    literals: &'a [usize],
+    /// The matching entries behind the prefilter, in ascending order.
+    candidates: Vec<usize>,
+    indices: &'a [usize],
+    shards: shard::Matches<H>,
+}
+
+impl<'a, H: ?Sized + Haystack + 'a> Matches<'a, H> {
+    pub(crate) fn matched(&self, index: usize) -> bool {
+        if self.literals.binary_search(&index).is_ok()
+            || self.candidates.binary_search(&index).is_ok()
+        {
+            return true;
+        }
+        match self.indices.binary_search(&index) {
+            Ok(position) => self.shards.matched(position),
+            Err(_) => false,
+        }
+    }
+
+    /// Turn into an iterator over the matched indices, in ascending order.
+    pub(crate) fn into_indices(self) -> impl Iterator<Item = usize> + 'a {
+        let indices = self.indices;
+        let literals = Merge {
+            a: self.literals.iter().copied().peekable(),
+            b: self.candidates.into_iter().peekable(),
+        };
+        Merge {
+            a: literals.peekable(),
+            b: self
+                .shards
+                .into_indices()
+                .map(move |position| indices[position])
+                .peekable(),
+        }
+    }
+}
+
+/// Merge two iterators over ascending indices into one.
+struct Merge<A: Iterator, B: Iterator> {
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<A, B> Iterator for Merge<A, B>
+where
+    A: Iterator<Item = usize>,
+    B: Iterator<Item = usize>,
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) if b < a => self.b.next(),
+            (Some(_), _) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+/// The string matched by `hir`, if it matches exactly one string and only as the whole key, i.e. it is
+/// a literal anchored at both ends like `^example\.com$`.
+fn exact_literal(hir: &Hir) -> Option<Vec<u8>> {
+    let HirKind::Concat(hirs) = hir.kind() else {
+        return None;
+    };
+    match hirs.as_slice() {
+        [start, end] if is_look(start, Look::Start) && is_look(end, Look::End) => Some(Vec::new()),
+        [start, literal, end] if is_look(start, Look::Start) && is_look(end, Look::End) => {
+            match literal.kind() {
+                HirKind::Literal(literal) => Some(literal.0.to_vec()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_look(hir: &Hir, look: Look) -> bool {
+    matches!(hir.kind(), HirKind::Look(l) if *l == look)
+}
+
+/// Literals one of which occurs in every match of `hir`, if there are few enough of them and none is
+/// too short to be selective.
+///
+/// The prefixes of the matches are preferred, as they tend to tell regular expressions apart better than
+/// their suffixes, which are only used when the prefixes do not qualify.
+fn required_literals(hir: &Hir) -> Option<Seq> {
+    let mut prefixes = Extractor::new().kind(ExtractKind::Prefix).extract(hir);
+    prefixes.optimize_for_prefix_by_preference();
+    if selective(&prefixes) {
+        return Some(prefixes);
+    }
+    let mut suffixes = Extractor::new().kind(ExtractKind::Suffix).extract(hir);
+    suffixes.optimize_for_suffix_by_preference();
+    selective(&suffixes).then_some(suffixes)
+}
+
+fn selective(seq: &Seq) -> bool {
+    seq.min_literal_len()
+        .is_some_and(|len| len >= PREFILTER_MIN_LITERAL_LEN)
+}
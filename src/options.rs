@@ -0,0 +1,78 @@
+use crate::pattern::Anchor;
+
+/// Options shared by the `regex` set and the individual regular expressions of a `RegexMap`.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub(crate) case_insensitive: bool,
+    pub(crate) multi_line: bool,
+    pub(crate) dot_matches_new_line: bool,
+    pub(crate) unicode: bool,
+    pub(crate) size_limit: Option<usize>,
+    pub(crate) dfa_size_limit: Option<usize>,
+    pub(crate) anchor: Anchor,
+    /// Maximum number of entries per set, see `Shards`.
+    pub(crate) shard_len: Option<usize>,
+    /// Whether to prefilter on required literals, decided per map when unset.
+    pub(crate) prefilter: Option<bool>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            unicode: true,
+            size_limit: None,
+            dfa_size_limit: None,
+            anchor: Anchor::Unanchored,
+            shard_len: None,
+            prefilter: None,
+        }
+    }
+}
+
+/// Apply `Options` to any of the `regex` builders, which all share the same configuration methods.
+macro_rules! configure {
+    ($builder:expr, $options:expr) => {{
+        let options: &$crate::options::Options = $options;
+        let mut builder = $builder;
+        builder
+            .case_insensitive(options.case_insensitive)
+            .multi_line(options.multi_line)
+            .dot_matches_new_line(options.dot_matches_new_line)
+            .unicode(options.unicode);
+        if let Some(limit) = options.size_limit {
+            builder.size_limit(limit);
+        }
+        if let Some(limit) = options.dfa_size_limit {
+            builder.dfa_size_limit(limit);
+        }
+        builder
+    }};
+}
+
+pub(crate) use configure;
+
+impl Options {
+    /// A `regex_syntax` parser configured like the `regex` builders, for analysing regular expressions.
+    pub(crate) fn parser(&self, utf8: bool) -> regex_syntax::Parser {
+        regex_syntax::ParserBuilder::new()
+            .utf8(utf8)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .unicode(self.unicode)
+            .build()
+    }
+
+    /// The syntax configuration of the `regex-automata` builders, matching the `regex` builders.
+    pub(crate) fn syntax_config(&self, utf8: bool) -> regex_automata::util::syntax::Config {
+        regex_automata::util::syntax::Config::new()
+            .utf8(utf8)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .unicode(self.unicode)
+    }
+}
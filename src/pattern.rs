@@ -0,0 +1,242 @@
+#[cfg(feature = "serde")]
+use std::borrow::Cow;
+
+use regex_syntax::ast::{self, Ast};
+
+#[cfg(feature = "serde")]
+use crate::options::Options;
+
+/// Where the regular expressions of a `RegexMap` must match within a key, set with
+/// `RegexMapBuilder::anchor`.
+///
+/// The anchors are added around each regular expression on its syntax tree, so patterns containing
+/// alternations such as `foo|bar` are anchored as a whole.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    /// Match anywhere in the key. This is the default.
+    #[default]
+    Unanchored,
+    /// Match at the start of the key, i.e. the key must start with a match.
+    Prefix,
+    /// Match the whole key.
+    Full,
+}
+
+/// A regular expression together with flags that apply only to it, for use as a `RegexMap` key.
+///
+/// The flags are folded into the regular expression before the underlying set is built, so lookups
+/// work exactly as with a plain `&str` key. Anything `&str`-like converts into a `Pattern` without
+/// flags, which is what `RegexMap::new` accepts.
+///
+/// ```
+/// use regex_map::{Pattern, RegexMap};
+///
+/// let map = RegexMap::new([
+///    (Pattern::new("foo").case_insensitive(true), 1),
+///    (Pattern::new("bar"), 2),
+///    (Pattern::new("a.c").literal(true), 3),
+///    (Pattern::new("foo|bar").full_match(true), 4),
+/// ]);
+///
+/// assert_eq!(map.get("FOO bar").cloned().collect::<Vec<_>>(), vec![1, 2]);
+/// assert_eq!(map.get("abc").cloned().collect::<Vec<_>>(), Vec::<i32>::new());
+/// assert_eq!(map.get("a.c").cloned().collect::<Vec<_>>(), vec![3]);
+/// assert_eq!(map.get("bar").cloned().collect::<Vec<_>>(), vec![2, 4]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    source: String,
+    case_insensitive: bool,
+    multi_line: bool,
+    full_match: bool,
+    literal: bool,
+}
+
+impl Pattern {
+    /// Create a new `Pattern` from a regular expression, with no flags set.
+    pub fn new<S: AsRef<str>>(source: S) -> Self {
+        Pattern {
+            source: source.as_ref().to_owned(),
+            case_insensitive: false,
+            multi_line: false,
+            full_match: false,
+            literal: false,
+        }
+    }
+
+    /// The regular expression this pattern was created from, without any of its flags applied.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Set the case insensitive (`i`) flag for this regular expression only.
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Set the multi-line matching (`m`) flag for this regular expression only.
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.multi_line = yes;
+        self
+    }
+
+    /// Require the regular expression to match the whole key rather than any part of it.
+    pub fn full_match(mut self, yes: bool) -> Self {
+        self.full_match = yes;
+        self
+    }
+
+    /// Treat the source as a literal string rather than a regular expression.
+    pub fn literal(mut self, yes: bool) -> Self {
+        self.literal = yes;
+        self
+    }
+
+    /// The regular expression with the flags folded in, as handed to the underlying set.
+    ///
+    /// The flags are applied on the syntax tree rather than by string concatenation, so alternations,
+    /// comments in `x` mode and inline flags in the source keep their meaning. A source that does not
+    /// parse is returned as is, so that building the set reports the error.
+    ///
+    /// `anchor` is the anchoring of the whole map; a pattern with `full_match` set is always anchored at
+    /// both ends.
+    pub(crate) fn to_regex(&self, anchor: Anchor) -> String {
+        let source = match self.literal {
+            true => regex::escape(&self.source),
+            false => self.source.clone(),
+        };
+        let anchor = match self.full_match {
+            true => Anchor::Full,
+            false => anchor,
+        };
+        if !self.case_insensitive && !self.multi_line && anchor == Anchor::Unanchored {
+            return source;
+        }
+
+        let mut ast = match ast::parse::Parser::new().parse(&source) {
+            Ok(ast) => ast,
+            Err(_) => return source,
+        };
+        let mut flags = Vec::new();
+        if self.case_insensitive {
+            flags.push(ast::FlagsItemKind::Flag(ast::Flag::CaseInsensitive));
+        }
+        if self.multi_line {
+            flags.push(ast::FlagsItemKind::Flag(ast::Flag::MultiLine));
+        }
+        if !flags.is_empty() || anchor != Anchor::Unanchored {
+            ast = group(ast, &flags);
+        }
+        match anchor {
+            Anchor::Unanchored => {}
+            Anchor::Prefix => {
+                ast = Ast::concat(ast::Concat {
+                    span: span(),
+                    asts: vec![assertion(ast::AssertionKind::StartText), ast],
+                });
+            }
+            Anchor::Full => {
+                ast = Ast::concat(ast::Concat {
+                    span: span(),
+                    asts: vec![
+                        assertion(ast::AssertionKind::StartText),
+                        ast,
+                        assertion(ast::AssertionKind::EndText),
+                    ],
+                });
+            }
+        }
+
+        print(&ast)
+    }
+
+    /// Parse the source on its own with this pattern's flags, returning the syntax error if it is invalid.
+    ///
+    /// Unlike building the set, the error points into the source rather than the folded regular
+    /// expression.
+    pub(crate) fn syntax_error(&self, utf8: bool) -> Option<regex_syntax::Error> {
+        if self.literal {
+            return None;
+        }
+        regex_syntax::ParserBuilder::new()
+            .utf8(utf8)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .build()
+            .parse(&self.source)
+            .err()
+    }
+}
+
+impl<S: AsRef<str>> From<S> for Pattern {
+    fn from(source: S) -> Self {
+        Pattern::new(source)
+    }
+}
+
+/// `expr` with the map-wide flags of `options` folded in, so that it matches the same keys in a map
+/// built with the default options.
+///
+/// As in `Pattern::to_regex`, the flags are applied on the syntax tree and a regular expression that
+/// does not parse is returned as is.
+#[cfg(feature = "serde")]
+pub(crate) fn fold_options<'a>(expr: &'a str, options: &Options) -> Cow<'a, str> {
+    let mut flags = Vec::new();
+    if options.case_insensitive {
+        flags.push(ast::FlagsItemKind::Flag(ast::Flag::CaseInsensitive));
+    }
+    if options.multi_line {
+        flags.push(ast::FlagsItemKind::Flag(ast::Flag::MultiLine));
+    }
+    if options.dot_matches_new_line {
+        flags.push(ast::FlagsItemKind::Flag(ast::Flag::DotMatchesNewLine));
+    }
+    if !options.unicode {
+        flags.push(ast::FlagsItemKind::Negation);
+        flags.push(ast::FlagsItemKind::Flag(ast::Flag::Unicode));
+    }
+    if flags.is_empty() {
+        return Cow::Borrowed(expr);
+    }
+    match ast::parse::Parser::new().parse(expr) {
+        Ok(ast) => Cow::Owned(print(&group(ast, &flags))),
+        Err(_) => Cow::Borrowed(expr),
+    }
+}
+
+fn print(ast: &Ast) -> String {
+    let mut regex = String::new();
+    ast::print::Printer::new()
+        .print(ast, &mut regex)
+        .expect("printing to a string should not fail");
+    regex
+}
+
+/// Wrap `ast` in a non-capturing group that sets `flags`.
+fn group(ast: Ast, flags: &[ast::FlagsItemKind]) -> Ast {
+    Ast::group(ast::Group {
+        span: span(),
+        kind: ast::GroupKind::NonCapturing(ast::Flags {
+            span: span(),
+            items: flags
+                .iter()
+                .map(|flag| ast::FlagsItem {
+                    span: span(),
+                    kind: flag.clone(),
+                })
+                .collect(),
+        }),
+        ast: Box::new(ast),
+    })
+}
+
+fn assertion(kind: ast::AssertionKind) -> Ast {
+    Ast::assertion(ast::Assertion { span: span(), kind })
+}
+
+/// Placeholder span for syntax nodes the crate synthesizes; the printer ignores spans.
+fn span() -> ast::Span {
+    let position = ast::Position::new(0, 1, 1);
+    ast::Span::new(position, position)
+}
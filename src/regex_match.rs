@@ -0,0 +1,9 @@
+/// Classify strings into the variants of an enum, usually implemented with `#[derive(RegexMatch)]` from
+/// the `macros` feature.
+pub trait RegexMatch: Sized {
+    /// Get an iterator over all variants whose regular expressions match `s`, in declaration order.
+    fn matches(s: &str) -> impl Iterator<Item = Self>;
+
+    /// Get the first variant, in declaration order, whose regular expressions match `s`.
+    fn first_match(s: &str) -> Option<Self>;
+}
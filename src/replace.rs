@@ -0,0 +1,73 @@
+use crate::haystack::Haystack;
+
+/// The replacement for the matches of an entry, as the value of a map passed to
+/// `RegexMap::replace_all`.
+///
+/// It is implemented for templates, `&str` and `String` (`&[u8]` and `Vec<u8>` for
+/// `regex_map::bytes::RegexMap`), where `$1` or `$name` expand to the captures of the regular
+/// expression of the entry, as in `regex::Regex::replace_all`. It is also implemented for closures
+/// computing the replacement from the captures, whose result is inserted as is.
+///
+/// Closures of different types can be stored in the same map when boxed:
+///
+/// ```
+/// use regex::Captures;
+/// use regex_map::RegexMap;
+///
+/// type Replace = Box<dyn Fn(&Captures) -> String>;
+///
+/// let map = RegexMap::<Replace>::new([
+///    ("[0-9]+", Box::new(|caps: &Captures| caps[0].len().to_string()) as Replace),
+///    ("[a-z]+", Box::new(|caps: &Captures| caps[0].to_uppercase())),
+/// ]);
+///
+/// assert_eq!(map.replace_all("id 1234"), "ID 4");
+/// ```
+pub trait Replacement<H: ?Sized + Haystack> {
+    /// Append the replacement for the match described by `captures` to `dst`.
+    fn replace_append(&self, captures: &H::Captures<'_>, dst: &mut H::Owned);
+}
+
+impl Replacement<str> for &str {
+    fn replace_append(&self, captures: &regex::Captures<'_>, dst: &mut String) {
+        captures.expand(self, dst);
+    }
+}
+
+impl Replacement<str> for String {
+    fn replace_append(&self, captures: &regex::Captures<'_>, dst: &mut String) {
+        captures.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacement<str> for F
+where
+    F: Fn(&regex::Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&self, captures: &regex::Captures<'_>, dst: &mut String) {
+        dst.push_str(self(captures).as_ref());
+    }
+}
+
+impl Replacement<[u8]> for &[u8] {
+    fn replace_append(&self, captures: &regex::bytes::Captures<'_>, dst: &mut Vec<u8>) {
+        captures.expand(self, dst);
+    }
+}
+
+impl Replacement<[u8]> for Vec<u8> {
+    fn replace_append(&self, captures: &regex::bytes::Captures<'_>, dst: &mut Vec<u8>) {
+        captures.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacement<[u8]> for F
+where
+    F: Fn(&regex::bytes::Captures<'_>) -> T,
+    T: AsRef<[u8]>,
+{
+    fn replace_append(&self, captures: &regex::bytes::Captures<'_>, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(self(captures).as_ref());
+    }
+}
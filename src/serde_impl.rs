@@ -0,0 +1,151 @@
+//! `Serialize` and `Deserialize` implementations, enabled by the `serde` feature.
+//!
+//! A `RegexMap` serializes as a sequence of `{"pattern": ..., "value": ...}` objects, in insertion order.
+//! It deserializes from that form as well as from a map of patterns to values, whose order is kept.
+//!
+//! The flags set on `RegexMapBuilder`, such as `case_insensitive`, are folded into each written pattern
+//! and entries with a non-zero priority get a `"priority"` field, so the map read back matches the same
+//! keys. Options that do not change what is matched, such as the size limits, are not written.
+//!
+//! ```
+//! use regex_map::{Anchor, RegexMap, RegexMapBuilder};
+//!
+//! let map: RegexMap<i32> = serde_json::from_str(r#"[
+//!     {"pattern": "foo", "value": 1},
+//!     {"pattern": "bar", "value": 2}
+//! ]"#).unwrap();
+//! assert_eq!(map.get("foobar").cloned().collect::<Vec<_>>(), vec![1, 2]);
+//!
+//! let map: RegexMap<i32> = serde_json::from_str(r#"{"foo": 1, "bar": 2}"#).unwrap();
+//! assert_eq!(map.get("foobar").cloned().collect::<Vec<_>>(), vec![1, 2]);
+//!
+//! let json = serde_json::to_string(&map).unwrap();
+//! assert_eq!(json, r#"[{"pattern":"foo","value":1},{"pattern":"bar","value":2}]"#);
+//!
+//! let map = RegexMapBuilder::new([("foo", 1), ("fo+", 2)])
+//!     .anchor(Anchor::Full)
+//!     .case_insensitive(true)
+//!     .priority(1, 5)
+//!     .build()
+//!     .unwrap();
+//! let json = serde_json::to_string(&map).unwrap();
+//! assert_eq!(json, r#"[{"pattern":"(?i:\\A(?:foo)\\z)","value":1},{"pattern":"(?i:\\A(?:fo+)\\z)","value":2,"priority":5}]"#);
+//!
+//! let map: RegexMap<i32> = serde_json::from_str(&json).unwrap();
+//! assert_eq!(map.get("FOO").cloned().collect::<Vec<_>>(), vec![1, 2]);
+//! assert_eq!(map.get_first("FOO"), Some(&2));
+//! assert_eq!(map.get("xfoo").next(), None);
+//! ```
+//!
+//! Invalid patterns are reported as deserialization errors, at the position of the offending entry:
+//!
+//! ```
+//! use regex_map::RegexMap;
+//!
+//! let error = serde_json::from_str::<RegexMap<i32>>(r#"[
+//!     {"pattern": "foo", "value": 1},
+//!     {"pattern": "(bar", "value": 2}
+//! ]"#).unwrap_err();
+//! assert_eq!(error.line(), 3);
+//! assert!(error.to_string().contains("`(bar`"));
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::haystack::Haystack;
+use crate::map::{RegexMap, RegexMapBuilder};
+use crate::pattern::{self, Pattern};
+
+#[derive(serde::Serialize)]
+struct EntryRef<'a, V> {
+    pattern: Cow<'a, str>,
+    value: &'a V,
+    #[serde(skip_serializing_if = "is_zero")]
+    priority: i32,
+}
+
+fn is_zero(priority: &i32) -> bool {
+    *priority == 0
+}
+
+#[derive(serde::Deserialize)]
+#[serde(bound(deserialize = "V: Deserialize<'de>"))]
+struct Entry<V, H: ?Sized + Haystack> {
+    pattern: CheckedPattern<H>,
+    value: V,
+    #[serde(default)]
+    priority: i32,
+}
+
+/// A pattern whose syntax is checked as soon as it is deserialized, so that an error points at it.
+struct CheckedPattern<H: ?Sized>(String, PhantomData<H>);
+
+impl<'de, H: ?Sized + Haystack> Deserialize<'de> for CheckedPattern<H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        match Pattern::new(&pattern).syntax_error(H::UTF8) {
+            Some(error) => Err(de::Error::custom(format_args!(
+                "invalid regular expression `{}`: {}",
+                pattern, error
+            ))),
+            None => Ok(CheckedPattern(pattern, PhantomData)),
+        }
+    }
+}
+
+impl<V: Serialize, H: ?Sized + Haystack> Serialize for RegexMap<V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().zip(self.priorities()).map(
+            |((pattern, value), &priority)| EntryRef {
+                pattern: pattern::fold_options(pattern, self.options()),
+                value,
+                priority,
+            },
+        ))
+    }
+}
+
+impl<'de, V: Deserialize<'de>, H: ?Sized + Haystack> Deserialize<'de> for RegexMap<V, H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RegexMapVisitor(PhantomData, PhantomData))
+    }
+}
+
+struct RegexMapVisitor<V, H: ?Sized>(PhantomData<V>, PhantomData<H>);
+
+impl<'de, V: Deserialize<'de>, H: ?Sized + Haystack> Visitor<'de> for RegexMapVisitor<V, H> {
+    type Value = RegexMap<V, H>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of {pattern, value} objects or a map of patterns to values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        let mut priorities = Vec::with_capacity(items.capacity());
+        while let Some(entry) = seq.next_element::<Entry<V, H>>()? {
+            items.push((entry.pattern.0, entry.value));
+            priorities.push(entry.priority);
+        }
+        let builder = priorities
+            .into_iter()
+            .enumerate()
+            .fold(RegexMapBuilder::new(items), |builder, (index, priority)| {
+                builder.priority(index, priority)
+            });
+        builder.build().map_err(de::Error::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(pattern) = map.next_key::<CheckedPattern<H>>()? {
+            items.push((pattern.0, map.next_value()?));
+        }
+        RegexMap::try_new(items).map_err(de::Error::custom)
+    }
+}
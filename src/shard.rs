@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use crate::error::{self, Error};
+use crate::haystack::{Haystack, SetMatches};
+use crate::options::Options;
+
+/// A list of regular expressions, split across one or more sets.
+///
+/// Each set holds a contiguous range of the list, so the position of a regular expression is the start
+/// of its set plus its index within the set, and iterating the sets in order yields matches in
+/// ascending order.
+pub(crate) struct Shards<H: ?Sized + Haystack> {
+    sets: Vec<H::Set>,
+    /// The range of entries held by each set.
+    ranges: Vec<Range<usize>>,
+}
+
+impl<H: ?Sized + Haystack> Shards<H> {
+    /// Build sets of at most `options.shard_len` entries, halving any that exceed `options.size_limit`.
+    pub(crate) fn build(exprs: &[String], options: &Options) -> Result<Self, Error> {
+        let mut shards = Shards::empty();
+        let chunk = options.shard_len.unwrap_or(usize::MAX).max(1);
+        let mut start = 0;
+        while start < exprs.len() {
+            let end = exprs.len().min(start.saturating_add(chunk));
+            shards.push(exprs, start..end, options)?;
+            start = end;
+        }
+        Ok(shards)
+    }
+
+    pub(crate) fn empty() -> Self {
+        Shards {
+            sets: Vec::new(),
+            ranges: Vec::new(),
+        }
+    }
+
+    fn push(
+        &mut self,
+        exprs: &[String],
+        range: Range<usize>,
+        options: &Options,
+    ) -> Result<(), Error> {
+        match H::build_set(&exprs[range.clone()], options) {
+            Ok(set) => {
+                self.sets.push(set);
+                self.ranges.push(range);
+                Ok(())
+            }
+            Err(regex::Error::CompiledTooBig(_)) if range.len() > 1 => {
+                let mid = range.start + range.len() / 2;
+                self.push(exprs, range.start..mid, options)?;
+                self.push(exprs, mid..range.end, options)
+            }
+            Err(error) => {
+                let mut error = error::locate(&exprs[range.clone()], error, |expr| {
+                    H::build_regex(expr, options).map(|_| ())
+                });
+                if let Error::Pattern { index, .. } = &mut error {
+                    *index += range.start;
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// The range of entries held by each set, in order.
+    pub(crate) fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    pub(crate) fn matches(&self, key: &H) -> Matches<H> {
+        Matches {
+            shards: self
+                .ranges
+                .iter()
+                .zip(&self.sets)
+                .map(|(range, set)| (range.clone(), H::matches(set, key)))
+                .filter(|(_, matches)| matches.matched_any())
+                .collect(),
+        }
+    }
+
+    /// The lowest position below `end` of a regular expression matching `key`, searching the sets in
+    /// order and stopping at the first that matches.
+    pub(crate) fn first(&self, key: &H, end: usize) -> Option<usize> {
+        self.ranges
+            .iter()
+            .zip(&self.sets)
+            .take_while(|(range, _)| range.start < end)
+            .find(|(_, set)| H::is_match(set, key))
+            .and_then(|(range, set)| {
+                let first = H::matches(set, key).into_indices().next()?;
+                Some(range.start + first).filter(|&first| first < end)
+            })
+    }
+
+    pub(crate) fn is_match(&self, key: &H) -> bool {
+        self.sets.iter().any(|set| H::is_match(set, key))
+    }
+}
+
+impl<H: ?Sized + Haystack> Clone for Shards<H> {
+    fn clone(&self) -> Self {
+        Shards {
+            sets: self.sets.clone(),
+            ranges: self.ranges.clone(),
+        }
+    }
+}
+
+/// The regular expressions matched by `Shards`, by position in the list.
+pub(crate) struct Matches<H: ?Sized + Haystack> {
+    /// The range and matches of every set that matched anything.
+    shards: Vec<(Range<usize>, H::SetMatches)>,
+}
+
+impl<H: ?Sized + Haystack> Matches<H> {
+    pub(crate) fn matched(&self, index: usize) -> bool {
+        let shard = self.shards.partition_point(|(range, _)| range.end <= index);
+        match self.shards.get(shard) {
+            Some((range, matches)) => {
+                range.contains(&index) && matches.matched(index - range.start)
+            }
+            None => false,
+        }
+    }
+
+    /// Turn into an iterator over the matched indices, in ascending order.
+    pub(crate) fn into_indices(self) -> impl Iterator<Item = usize> {
+        self.shards
+            .into_iter()
+            .flat_map(|(range, matches)| matches.into_indices().map(move |i| range.start + i))
+    }
+}
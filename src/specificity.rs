@@ -0,0 +1,100 @@
+use std::cmp::Ordering;
+
+use regex_syntax::ast::{self, Ast};
+use regex_syntax::hir::{Look, LookSet};
+
+use crate::options::Options;
+
+/// How `RegexMap::get_most_specific` picks among several matching entries.
+///
+/// Whichever strategy is used, ties are broken in favour of the entry inserted first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Specificity {
+    /// The entry whose regular expression has the longest match in the key, among the matches starting where
+    /// its leftmost match starts; e.g. `a|abc` counts as 3 in `abc`, not 1.
+    LongestMatch,
+    /// The entry whose regular expression contains the most literal characters, e.g. `/api/users/.*`
+    /// over `/api/.*`.
+    MostLiterals,
+    /// Entries anchored at both ends of the key beat those anchored at one end, which beat unanchored
+    /// ones. Line anchors in multi-line mode count as well.
+    Anchored,
+}
+
+/// A matching entry, handed to the comparator of `RegexMap::get_most_specific_by`.
+#[derive(Debug)]
+pub struct Entry<'a, V> {
+    /// Index of the entry, in insertion order.
+    pub index: usize,
+    /// The regular expression of the entry.
+    pub pattern: &'a str,
+    /// The value of the entry.
+    pub value: &'a V,
+}
+
+/// Properties of a regular expression used to rank it, computed on first use.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Specifics {
+    pub(crate) literals: usize,
+    pub(crate) anchors: usize,
+}
+
+impl Specifics {
+    pub(crate) fn new(expr: &str, options: &Options, utf8: bool) -> Self {
+        let literals = ast::parse::Parser::new()
+            .parse(expr)
+            .ok()
+            .and_then(|ast| ast::visit(&ast, LiteralCounter(0)).ok())
+            .unwrap_or(0);
+        let anchors = match options.parser(utf8).parse(expr) {
+            Ok(hir) => {
+                let properties = hir.properties();
+                let start = LookSet::empty()
+                    .insert(Look::Start)
+                    .insert(Look::StartLF)
+                    .insert(Look::StartCRLF);
+                let end = LookSet::empty()
+                    .insert(Look::End)
+                    .insert(Look::EndLF)
+                    .insert(Look::EndCRLF);
+                usize::from(!properties.look_set_prefix().intersect(start).is_empty())
+                    + usize::from(!properties.look_set_suffix().intersect(end).is_empty())
+            }
+            Err(_) => 0,
+        };
+        Specifics { literals, anchors }
+    }
+}
+
+/// Pick the index whose key is greatest, preferring earlier indices on ties.
+pub(crate) fn max_by<I, F>(indices: I, mut compare: F) -> Option<usize>
+where
+    I: IntoIterator<Item = usize>,
+    F: FnMut(usize, usize) -> Ordering,
+{
+    indices
+        .into_iter()
+        .reduce(|best, i| match compare(i, best) {
+            Ordering::Greater => i,
+            _ => best,
+        })
+}
+
+struct LiteralCounter(usize);
+
+impl ast::Visitor for LiteralCounter {
+    type Output = usize;
+    type Err = ();
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+        if let Ast::Literal(_) = ast {
+            self.0 += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<usize, ()> {
+        Ok(self.0)
+    }
+}
@@ -0,0 +1,12 @@
+/// Statistics about how a `RegexMap` was compiled, returned by `RegexMap::stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of entries answered from the hash table of exact literals.
+    pub literals: usize,
+    /// The number of entries only searched for when the key contains one of their required literals,
+    /// see `RegexMapBuilder::prefilter`.
+    pub prefiltered: usize,
+    /// The number of regular expressions in each underlying set, in order.
+    pub shards: Vec<usize>,
+}
@@ -0,0 +1,171 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::ops::Range;
+
+use regex_automata::hybrid::dfa::Cache;
+use regex_automata::Input;
+
+use crate::find::OverlappingFinder;
+use crate::haystack::Haystack;
+use crate::map::RegexMap;
+
+/// Lexer splitting an input into tokens, whose kinds are the values of a `RegexMap`.
+///
+/// This is the implementation shared by `regex_map::Tokenizer` and `regex_map::bytes::Tokenizer`.
+///
+/// At each position, the token is the longest non-empty match of any regular expression, ties broken
+/// in favour of the entry with the highest priority (see `RegexMap::set_priority`), then the entry
+/// inserted first. All regular expressions are searched for at once, with lazy DFAs built on first use.
+pub struct Tokenizer<V, H: ?Sized + Haystack> {
+    map: RegexMap<V, H>,
+    trivia: fn(&V) -> bool,
+}
+
+impl<V, H: ?Sized + Haystack> Tokenizer<V, H> {
+    /// Create a new `Tokenizer` whose token kinds are the values of `map`.
+    pub fn new(map: RegexMap<V, H>) -> Self {
+        Tokenizer {
+            map,
+            trivia: |_| false,
+        }
+    }
+
+    /// Set which token kinds are trivia, like whitespace or comments, matched but not yielded. Defaults
+    /// to none.
+    pub fn trivia(mut self, is_trivia: fn(&V) -> bool) -> Self {
+        self.trivia = is_trivia;
+        self
+    }
+
+    /// Get an iterator over the tokens of `input`, other than trivia.
+    ///
+    /// When no regular expression matches at some position, the iterator yields a `TokenError` at that
+    /// position and ends.
+    ///
+    /// ```
+    /// use regex_map::{RegexMap, Token, TokenError, Tokenizer};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Kind { Ident, Keyword, Number, Space }
+    ///
+    /// let mut map = RegexMap::new([
+    ///    ("[a-z]+", Kind::Ident),
+    ///    ("let|in", Kind::Keyword),
+    ///    ("[0-9]+", Kind::Number),
+    ///    (r"\s+", Kind::Space),
+    /// ]);
+    /// map.set_priority(1, 1);
+    /// let tokenizer = Tokenizer::new(map).trivia(|kind| *kind == Kind::Space);
+    ///
+    /// assert_eq!(tokenizer.tokenize("let inner 42").collect::<Vec<_>>(), vec![
+    ///     Ok(Token { span: 0..3, value: &Kind::Keyword }),
+    ///     Ok(Token { span: 4..9, value: &Kind::Ident }),
+    ///     Ok(Token { span: 10..12, value: &Kind::Number }),
+    /// ]);
+    /// assert_eq!(tokenizer.tokenize("let x = 1").collect::<Vec<_>>(), vec![
+    ///     Ok(Token { span: 0..3, value: &Kind::Keyword }),
+    ///     Ok(Token { span: 4..5, value: &Kind::Ident }),
+    ///     Err(TokenError { position: 6 }),
+    /// ]);
+    /// ```
+    ///
+    /// Unicode word boundaries (`\b` with Unicode enabled) are supported on non-ASCII input, although
+    /// every token of an entry that has any is confirmed by its regular expression alone:
+    ///
+    /// ```
+    /// use regex_map::{RegexMap, Token, Tokenizer};
+    ///
+    /// let map = RegexMap::new([(r"[a-z]+\b", "word"), (r"\s+", "space"), (r"\S", "char")]);
+    /// let tokenizer = Tokenizer::new(map).trivia(|kind| *kind == "space");
+    ///
+    /// assert_eq!(tokenizer.tokenize("ab hé").collect::<Vec<_>>(), vec![
+    ///     Ok(Token { span: 0..2, value: &"word" }),
+    ///     Ok(Token { span: 3..4, value: &"char" }),
+    ///     Ok(Token { span: 4..6, value: &"char" }),
+    /// ]);
+    /// ```
+    pub fn tokenize<'a>(&'a self, input: &'a H) -> Tokens<'a, V, H> {
+        let finder = self.map.overlapping();
+        Tokens {
+            tokenizer: self,
+            finder,
+            caches: finder.create_caches(),
+            input: Input::new(H::as_bytes(input)),
+            done: false,
+        }
+    }
+
+    /// The underlying map.
+    pub fn map(&self) -> &RegexMap<V, H> {
+        &self.map
+    }
+
+    /// Turn into the underlying map.
+    pub fn into_map(self) -> RegexMap<V, H> {
+        self.map
+    }
+}
+
+/// Iterator over the tokens of an input, created by `Tokenizer::tokenize`.
+///
+/// The caches of the automata are created with the iterator and reused for every token.
+pub struct Tokens<'a, V, H: ?Sized + Haystack> {
+    tokenizer: &'a Tokenizer<V, H>,
+    finder: &'a OverlappingFinder,
+    caches: Vec<Cache>,
+    /// The rest of the input.
+    input: Input<'a>,
+    done: bool,
+}
+
+impl<'a, V, H: ?Sized + Haystack> Iterator for Tokens<'a, V, H> {
+    type Item = Result<Token<'a, V>, TokenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let map = &self.tokenizer.map;
+        let priorities = map.priorities();
+        while !self.done && self.input.start() < self.input.end() {
+            let start = self.input.start();
+            let longest = self.finder.longest_at(&mut self.caches, &self.input, |i| {
+                (priorities[i], Reverse(i))
+            });
+            let Some((index, end)) = longest else {
+                self.done = true;
+                return Some(Err(TokenError { position: start }));
+            };
+            self.input.set_start(end);
+            let value = map.value(index);
+            if !(self.tokenizer.trivia)(value) {
+                return Some(Ok(Token {
+                    span: start..end,
+                    value,
+                }));
+            }
+        }
+        None
+    }
+}
+
+/// A token yielded by `Tokens`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a, V> {
+    /// Byte range of the token in the input.
+    pub span: Range<usize>,
+    /// The value of the entry that matched the token.
+    pub value: &'a V,
+}
+
+/// Error yielded by `Tokens` when no regular expression matches at some position of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenError {
+    /// Byte offset in the input where no token starts.
+    pub position: usize,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no token matches at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for TokenError {}